@@ -0,0 +1,15 @@
+use boards::busicom141pf;
+
+fn main() {
+  let mut board = busicom141pf::Board::new(include_bytes!("../../busicom141pf/roms/4001-0009.bin").to_vec(),
+                                           include_bytes!("../../busicom141pf/roms/4001-0010.bin").to_vec(),
+                                           include_bytes!("../../busicom141pf/roms/4001-0011.bin").to_vec(),
+                                           include_bytes!("../../busicom141pf/roms/4001-0012.bin").to_vec(),
+                                           include_bytes!("../../busicom141pf/roms/4001-0013.bin").to_vec());
+  let _ = simple_logger::init_with_level(log::Level::Warn);
+  board.enable_paper_tape();
+  board.run_script("12 + 34 =");
+  for line in board.paper_tape() {
+    println!("{}", line);
+  }
+}
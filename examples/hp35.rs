@@ -1,20 +1,13 @@
-use boards::hp35;
-
-use arbitrary_int::u10;
+use boards::hp_classic::{self, HP35Key, RunCycles};
 
 fn main() {
-  let mut board = hp35::Board::new(to_u10(include_str!("../../hp35/roms/35v4.obj")));
+  let mut board = hp_classic::hp35(include_str!("../../hp35/roms/35v4.obj")).expect("Invalid ROM dump");
   let _ = simple_logger::init_with_level(log::Level::Trace);
-  for _ in 0..260 {
-    board.run_cycle(None);
+  board.set_trace(hp_classic::trace::log_trace);
+  board.run_cycles(2000);
+  for key in [HP35Key::Two, HP35Key::Enter, HP35Key::Three, HP35Key::Plus] {
+    board.press_key(key);
+    board.run_cycles(2000);
   }
+  println!("{}", board.display());
 }
-
-fn to_u10(contents: &str) -> Vec<u10> {
-  contents
-    .lines()
-    .filter_map(|line| {
-      Some(u10::new(u16::from_str_radix(line, 8).unwrap()))
-    })
-    .collect()
-}
\ No newline at end of file
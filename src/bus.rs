@@ -0,0 +1,228 @@
+//! Memory mapped bus, built from a table of address regions.
+//! A board declares which device answers each address range, and the bus finds the device and the offset within it.
+//! Chips stay owned by the board. On each access, the board passes a `Handler` which reaches the chips by device index.
+
+use std::ops::Range;
+
+use log::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+  /// Read only. Writes are reported and dropped.
+  Rom(usize),
+  Ram(usize),
+  /// Anything else with registers on the bus, such as I/O chips.
+  Device(usize),
+  /// Repeats the region starting at this address. The mirror's offset wraps around the `mirrored_len`.
+  Mirror { start: usize, mirrored_len: usize },
+  /// Nothing drives the data lines. Reads return the bus' open bus value, writes are ignored.
+  OpenBus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+  pub addresses: Range<usize>,
+  pub target: Target,
+}
+
+impl Region {
+  pub fn new(addresses: Range<usize>, target: Target) -> Self {
+    Self { addresses, target }
+  }
+}
+
+/// Where an address ended up, after following mirrors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoded {
+  pub target: Target,
+  /// Offset within the device.
+  pub offset: usize,
+}
+
+/// Gives the bus access to the board's chips.
+pub trait Handler {
+  fn read(&self, target: Target, offset: usize) -> u8;
+  fn write(&mut self, target: Target, offset: usize, value: u8);
+}
+
+#[derive(Debug, Clone)]
+pub struct Bus {
+  /// Used in warnings, to tell buses apart.
+  pub name: &'static str,
+  regions: Vec<Region>,
+  /// Value read from open bus and unmapped addresses.
+  pub open_bus: u8,
+  /// Length of the pages in `pages`. Every region and mirror starts and ends on a page boundary.
+  page_len: usize,
+  /// Decoded start of each page, so that `decode` is a lookup. Empty when the table would be too large.
+  pages: Vec<Option<Decoded>>,
+}
+
+/// Mirrors may point to mirrors, but not endlessly.
+const MAX_MIRROR_DEPTH: usize = 8;
+/// Larger address spaces are decoded by searching the regions.
+const MAX_PAGES: usize = 4096;
+
+fn gcd(a: usize, b: usize) -> usize {
+  if b == 0 { a } else { gcd(b, a % b) }
+}
+
+impl Bus {
+  /// Overlapping regions are reported. The first region declared wins.
+  pub fn new(name: &'static str, regions: Vec<Region>, open_bus: u8) -> Self {
+    for (index, region) in regions.iter().enumerate() {
+      for earlier in &regions[..index] {
+        if region.addresses.start < earlier.addresses.end && earlier.addresses.start < region.addresses.end {
+          warn!("{} bus: Region {:X?} overlaps {:X?}.", name, region.addresses, earlier.addresses);
+        }
+      }
+    }
+    let mut bus = Self { name, regions, open_bus, page_len: 1, pages: vec![] };
+    bus.build_pages();
+    bus
+  }
+  
+  pub fn regions(&self) -> &[Region] {
+    &self.regions
+  }
+  
+  /// Splits the address space into the largest pages which no region boundary falls into.
+  fn build_pages(&mut self) {
+    let end = self.regions.iter().map(|region| region.addresses.end).max().unwrap_or(0);
+    let page_len = self.regions.iter().fold(end, |page_len, region| {
+      let page_len = gcd(gcd(page_len, region.addresses.start), region.addresses.end);
+      match region.target {
+        Target::Mirror { start, mirrored_len } => gcd(gcd(page_len, start), mirrored_len),
+        _ => page_len,
+      }
+    });
+    if page_len == 0 || end / page_len > MAX_PAGES {
+      return;
+    }
+    self.pages = (0..end / page_len).map(|page| self.search(page * page_len)).collect();
+    self.page_len = page_len;
+  }
+  
+  /// Returns None for unmapped addresses.
+  pub fn decode(&self, address: usize) -> Option<Decoded> {
+    if self.pages.is_empty() {
+      return self.search(address);
+    }
+    let Decoded { target, offset } = (*self.pages.get(address / self.page_len)?)?;
+    Some(Decoded { target, offset: offset + address % self.page_len })
+  }
+  
+  fn search(&self, address: usize) -> Option<Decoded> {
+    let mut address = address;
+    for _ in 0..MAX_MIRROR_DEPTH {
+      let region = self.regions.iter().find(|region| region.addresses.contains(&address))?;
+      let offset = address - region.addresses.start;
+      match region.target {
+        Target::Mirror { start, mirrored_len } if mirrored_len > 0 => address = start + offset % mirrored_len,
+        Target::Mirror { .. } => return None,
+        target => return Some(Decoded { target, offset }),
+      }
+    }
+    warn!("{} bus: Mirrors nested too deep at address {:X}.", self.name, address);
+    None
+  }
+  
+  pub fn read(&self, address: usize, handler: &impl Handler) -> u8 {
+    match self.decode(address) {
+      Some(Decoded { target: Target::OpenBus, .. }) => self.open_bus,
+      Some(Decoded { target, offset }) => handler.read(target, offset),
+      None => {
+        warn!("{} bus: Read from unmapped address {:X}.", self.name, address);
+        self.open_bus
+      },
+    }
+  }
+  
+  pub fn write(&self, address: usize, value: u8, handler: &mut impl Handler) {
+    match self.decode(address) {
+      Some(Decoded { target: Target::Rom(_), .. }) => warn!("{} bus: Write of {:02X} to ROM address {:X}.", self.name, value, address),
+      Some(Decoded { target: Target::OpenBus, .. }) => {},
+      Some(Decoded { target, offset }) => handler.write(target, offset, value),
+      None => warn!("{} bus: Write of {:02X} to unmapped address {:X}.", self.name, value, address),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decoded(target: Target, offset: usize) -> Option<Decoded> {
+    Some(Decoded { target, offset })
+  }
+
+  /// 3 ROMs of 256 bytes, mirrored up to 4096, like a 4004 with 3 ROM chips.
+  fn rom_bus() -> Bus {
+    let mut regions: Vec<Region> = (0..3).map(|chip| Region::new(chip * 256..(chip + 1) * 256, Target::Rom(chip))).collect();
+    regions.push(Region::new(768..4096, Target::Mirror { start: 0, mirrored_len: 768 }));
+    Bus::new("test", regions, 0)
+  }
+
+  #[test]
+  fn mirror() {
+    let bus = rom_bus();
+    assert_eq!(bus.decode(0x105), decoded(Target::Rom(1), 5));
+    assert_eq!(bus.decode(768 + 0x205), decoded(Target::Rom(2), 5));
+    assert_eq!(bus.decode(4095), decoded(Target::Rom(0), 255));
+    assert_eq!(bus.decode(4095), bus.search(4095));
+  }
+
+  #[test]
+  fn overlap() {
+    let bus = Bus::new("test", vec![Region::new(0..0x100, Target::Ram(0)), Region::new(0x80..0x180, Target::Rom(0))], 0);
+    assert_eq!(bus.decode(0x90), decoded(Target::Ram(0), 0x90));
+    assert_eq!(bus.decode(0x150), decoded(Target::Rom(0), 0xD0));
+  }
+
+  #[test]
+  fn unmapped() {
+    let bus = Bus::new("test", vec![Region::new(0x100..0x200, Target::Ram(0)), Region::new(0x200..0x300, Target::OpenBus)], 0xFF);
+    assert_eq!(bus.decode(0x50), None);
+    assert_eq!(bus.decode(0x300), None);
+    assert_eq!(bus.decode(0x250), decoded(Target::OpenBus, 0x50));
+    assert_eq!(bus.decode(0x1000), bus.search(0x1000));
+  }
+
+  #[test]
+  fn mirror_loop() {
+    let bus = Bus::new("test", vec![Region::new(0..0x100, Target::Mirror { start: 0x100, mirrored_len: 0x100 }),
+                                    Region::new(0x100..0x200, Target::Mirror { start: 0, mirrored_len: 0x100 })], 0);
+    assert_eq!(bus.decode(0x10), None);
+  }
+
+  #[test]
+  fn large_address_space() {
+    let bus = Bus::new("test", vec![Region::new(0..0x10, Target::Ram(0)), Region::new(0x10..0x100000, Target::Rom(0))], 0);
+    assert!(bus.pages.is_empty());
+    assert_eq!(bus.decode(0x12345), decoded(Target::Rom(0), 0x12335));
+  }
+
+  struct Memory {
+    ram: [u8; 4],
+  }
+
+  impl Handler for Memory {
+    fn read(&self, _target: Target, offset: usize) -> u8 {
+      self.ram[offset]
+    }
+    fn write(&mut self, _target: Target, offset: usize, value: u8) {
+      self.ram[offset] = value;
+    }
+  }
+
+  #[test]
+  fn read_write() {
+    let bus = Bus::new("test", vec![Region::new(0..4, Target::Ram(0)), Region::new(4..8, Target::Rom(0))], 0xFF);
+    let mut memory = Memory { ram: [0; 4] };
+    bus.write(2, 0x42, &mut memory);
+    bus.write(6, 0x99, &mut memory);
+    assert_eq!(bus.read(2, &memory), 0x42);
+    assert_eq!(bus.read(6, &memory), 0x42);
+    assert_eq!(bus.read(9, &memory), 0xFF);
+  }
+}
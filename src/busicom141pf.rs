@@ -1,14 +1,115 @@
-use chips::{rom,ram,shifter,cpu,cpu::i4004};
+use std::fmt;
+
+use chips::{rom,ram,shifter,cpu};
 use log::warn;
-use arbitrary_int::{u2,u4};
+use arbitrary_int::u4;
+
+use crate::mcs4::{self, I4004IO};
+use crate::bus::Bus;
+
+pub mod state;
+pub use crate::mcs4::RamBanks;
 
 pub struct Board {
   pub i4001s: [rom::I4001; 5],
-  pub i4002s: [ram::I4002; 2],
+  /// Installed RAM chips, in bank order. Private, so that it always matches `ram_banks`. See `set_ram_banks` and `ram`.
+  i4002s: Vec<ram::I4002>,
   pub i4003s: [shifter::I4003; 3],
   pub i4004: cpu::I4004, 
+  pub wiring: Wiring,
   advance_paper: bool,
   hammering: bool,
+  paper_tape: Option<PaperTape>,
+  ram_banks: RamBanks,
+  rom_bus: Bus,
+}
+
+/// The 141-PF only has 2 RAM chips, both in bank 0.
+pub const BUSICOM_RAM_BANKS: RamBanks = [2, 0, 0, 0];
+
+const ROM_CHIP_LEN: usize = 256;
+const ROM_COUNT: usize = 5;
+
+#[derive(Debug)]
+pub enum RomError {
+  /// The combined image must be 5 * 256 = 1280 bytes.
+  WrongSize { expected: usize, actual: usize },
+  IntelHex(crate::intel_hex::Error),
+}
+
+impl fmt::Display for RomError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RomError::WrongSize { expected, actual } => write!(f, "ROM image is {} bytes, expected {} bytes", actual, expected),
+      RomError::IntelHex(error) => error.fmt(f),
+    }
+  }
+}
+
+impl std::error::Error for RomError {}
+
+impl From<crate::intel_hex::Error> for RomError {
+  fn from(error: crate::intel_hex::Error) -> Self {
+    RomError::IntelHex(error)
+  }
+}
+
+/// Port wiring, which differs slightly between the branded variants of the calculator.
+/// Bits are given as masks. ROM and RAM numbers which are not installed read as 0, and writes to them are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wiring {
+  /// ROM whose output port holds the shifter data and clocks.
+  pub shifter_rom: usize,
+  pub shifter_data: u8,
+  pub keyboard_clock: u8,
+  pub printer_clock: u8,
+  /// ROM whose input port reads the keyboard rows.
+  pub keyboard_rom: usize,
+  /// ROM whose input port reads the printer drum index signal.
+  pub printer_index_rom: usize,
+  pub printer_index: u8,
+  /// RAM whose output port drives the printer.
+  pub printer_ram: usize,
+  pub hammer: u8,
+  pub advance_paper: u8,
+  /// RAM whose output port drives the indicator lamps.
+  pub lamp_ram: usize,
+  pub memory_lamp: u8,
+  pub overflow_lamp: u8,
+  pub negative_lamp: u8,
+}
+
+impl Wiring {
+  pub const BUSICOM_141PF: Wiring = Wiring {
+    shifter_rom: 0,
+    shifter_data: 0b10,
+    keyboard_clock: 0b1,
+    printer_clock: 0b100,
+    keyboard_rom: 1,
+    printer_index_rom: 2,
+    printer_index: 0b1,
+    printer_ram: 0,
+    hammer: 0b10,
+    advance_paper: 0b1000,
+    lamp_ram: 1,
+    memory_lamp: 0b1,
+    overflow_lamp: 0b10,
+    negative_lamp: 0b100,
+  };
+}
+
+impl Default for Wiring {
+  fn default() -> Self {
+    Wiring::BUSICOM_141PF
+  }
+}
+
+/// Indicator lamps driven from the output port of the lamp RAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Lamps {
+  pub memory: bool,
+  pub overflow: bool,
+  pub negative: bool,
 }
 
 impl Board {
@@ -21,33 +122,146 @@ impl Board {
                 rom::I4001::new(binary3.try_into().unwrap()),
                 rom::I4001::new(binary4.try_into().unwrap())
               ],
-      i4002s: [ram::I4002::new(), ram::I4002::new()],
+      i4002s: vec![ram::I4002::new(), ram::I4002::new()],
       i4003s: [shifter::I4003::new(), shifter::I4003::new(), shifter::I4003::new()],
       i4004: cpu::I4004::new(),
+      wiring: Wiring::BUSICOM_141PF,
       advance_paper: false,
       hammering: false,
+      paper_tape: None,
+      ram_banks: BUSICOM_RAM_BANKS,
+      rom_bus: mcs4::rom_bus(ROM_COUNT),
     }
   }
+  
+  /// Loads all 5 ROMs from a single 1280 byte image, ROM 0 first.
+  pub fn from_image(image: &[u8]) -> Result<Self, RomError> {
+    if image.len() != ROM_CHIP_LEN * ROM_COUNT {
+      return Err(RomError::WrongSize { expected: ROM_CHIP_LEN * ROM_COUNT, actual: image.len() });
+    }
+    let mut chunks = image.chunks(ROM_CHIP_LEN).map(|chunk| chunk.to_vec());
+    Ok(Self::new(chunks.next().unwrap(), chunks.next().unwrap(), chunks.next().unwrap(), chunks.next().unwrap(), chunks.next().unwrap()))
+  }
+  
+  /// Loads all 5 ROMs from Intel HEX text, covering addresses 0 to 0x4FF.
+  pub fn from_intel_hex(text: &str) -> Result<Self, RomError> {
+    Self::from_image(&crate::intel_hex::parse(text, ROM_CHIP_LEN * ROM_COUNT)?)
+  }
+  
+  /// Replaces the RAM chips with a new population, up to 16 chips across the 4 banks. All RAM contents are cleared.
+  pub fn set_ram_banks(&mut self, mut ram_banks: RamBanks) {
+    self.i4002s = mcs4::populate_ram(&mut ram_banks);
+    self.ram_banks = ram_banks;
+  }
+  
+  pub fn ram_banks(&self) -> RamBanks {
+    self.ram_banks
+  }
+  
+  /// RAM chip by DCL bank and chip number within the bank. None if it is not installed.
+  pub fn ram(&self, bank: usize, chip: usize) -> Option<&ram::I4002> {
+    mcs4::ram_position(&self.ram_banks, bank, chip).map(|position| &self.i4002s[position])
+  }
 
   pub fn run_cycle(&mut self) {
+    if let Some(paper_tape) = &mut self.paper_tape {
+      paper_tape.drive_inputs(&self.wiring, &mut self.i4001s, &self.i4003s[0], &mut self.i4004);
+    }
+    
     //Make Rust happy by borrowing things one at a time, then releasing them when done.
     {
       let mut i4004_io = I4004IO {
         i4001s: &mut self.i4001s,
         i4002s: &mut self.i4002s,
+        ram_banks: &self.ram_banks,
+        program_ram: None,
+        rom_bus: &self.rom_bus,
+        #[cfg(feature = "i4040")]
+        interrupt: false,
+        #[cfg(feature = "i4040")]
+        rom_bank: None,
       };
       self.i4004.run_cycle(&mut i4004_io);
     }
     
-    //ROM 0 has shifter data and clocks
-    let ports = self.i4001s[0].read_ports().value();
+    //Shifter ROM has shifter data and clocks
+    let wiring = self.wiring;
+    let ports = self.rom_port(wiring.shifter_rom);
+    let data = ports & wiring.shifter_data == wiring.shifter_data;
     //Shifter 0 = Keyboard
-    self.i4003s[0].read_write_serial(shifter::Direction::Left, ports & 0b10 == 0, ports & 0b1 == 0);
+    self.i4003s[0].read_write_serial(shifter::Direction::Left, !data, ports & wiring.keyboard_clock == 0);
     
     //Shifter 1 = Printer
-    let out = self.i4003s[1].read_write_serial(shifter::Direction::Left, ports & 0b10 == 0b10, ports & 0b100 == 0);
+    let out = self.i4003s[1].read_write_serial(shifter::Direction::Left, data, ports & wiring.printer_clock == 0);
     //Shifter 2 = Cascade shifter 1, for Printer
-    self.i4003s[2].read_write_serial(shifter::Direction::Left, out, ports & 0b100 == 0);
+    self.i4003s[2].read_write_serial(shifter::Direction::Left, out, ports & wiring.printer_clock == 0);
+    
+    if self.paper_tape.is_some() {
+      let shift_bits = self.printer_shift_bits();
+      let printer_port = self.ram_port(wiring.printer_ram);
+      if let Some(paper_tape) = &mut self.paper_tape {
+        paper_tape.print(&wiring, shift_bits, printer_port);
+      }
+    }
+  }
+  
+  /// Headless mode. The board spins its own printer drum, scans the keys pressed with `press_key`,
+  /// and collects every printed line into an in-memory paper tape.
+  /// This takes over the TEST pin, and the input ports of the keyboard and printer index ROMs, so a GUI should not use it.
+  pub fn enable_paper_tape(&mut self) {
+    self.paper_tape = Some(PaperTape::new());
+  }
+  
+  /// All lines printed so far. Each line is the full 18 columns wide. Empty if paper tape mode is off.
+  pub fn paper_tape(&self) -> &[String] {
+    match &self.paper_tape {
+      Some(paper_tape) => &paper_tape.lines,
+      None => &[],
+    }
+  }
+  
+  /// Holds the key down until `release_key` is called. Only works in paper tape mode.
+  pub fn press_key(&mut self, key: Key) {
+    match &mut self.paper_tape {
+      Some(paper_tape) => paper_tape.key = Some(key),
+      None => warn!("Key {:?} pressed without paper tape mode.", key),
+    }
+  }
+  
+  pub fn release_key(&mut self) {
+    if let Some(paper_tape) = &mut self.paper_tape {
+      paper_tape.key = None;
+    }
+  }
+  
+  pub fn run_cycles(&mut self, cycles: u32) {
+    for _ in 0..cycles {
+      self.run_cycle();
+    }
+  }
+  
+  /// Types a keystroke script such as "12 + 34 =", giving the firmware time to react to each key.
+  /// Tokens are separated by spaces. A token is either a key name (see `Key::from_name`), or a run of single character key names.
+  pub fn run_script(&mut self, script: &str) {
+    for token in script.split_whitespace() {
+      if let Some(key) = Key::from_name(token) {
+        self.type_key(key);
+        continue;
+      }
+      for c in token.chars() {
+        match Key::from_name(&c.to_string()) {
+          Some(key) => self.type_key(key),
+          None => warn!("Unknown key {} in script.", c),
+        }
+      }
+    }
+  }
+  
+  fn type_key(&mut self, key: Key) {
+    self.press_key(key);
+    self.run_cycles(KEY_HOLD_CYCLES);
+    self.release_key();
+    self.run_cycles(KEY_SETTLE_CYCLES);
   }
   
   pub fn printer_shift_bits(&self) -> u32 {
@@ -56,9 +270,25 @@ impl Board {
     shift1 | (shift2 << 10)
   }
   
+  /// I/O port of a ROM chip, or 0 if there is no such ROM.
+  fn rom_port(&self, chip: usize) -> u8 {
+    match self.i4001s.get(chip) {
+      Some(i4001) => i4001.read_ports().value(),
+      None => 0,
+    }
+  }
+  
+  /// Output port of a RAM chip, or 0 if it is not populated.
+  fn ram_port(&self, chip: usize) -> u8 {
+    match self.i4002s.get(chip) {
+      Some(i4002) => i4002.read_ports().value(),
+      None => 0,
+    }
+  }
+  
   pub fn new_advance_paper_signal(&mut self) -> bool {
-    let ram0 = self.i4002s[0].read_ports().value();
-    let advance_paper = ram0 & 0b1000 == 0b1000;
+    let printer_port = self.ram_port(self.wiring.printer_ram);
+    let advance_paper = printer_port & self.wiring.advance_paper == self.wiring.advance_paper;
     if !self.advance_paper && advance_paper { //We only signal on the switch from false to true.
       self.advance_paper = true;
       return true;
@@ -70,8 +300,8 @@ impl Board {
   ///Returns false if not hammering.
   ///Returns true if hammering.
   pub fn new_hammer_signal(&mut self) -> bool  {
-    let ram0 = self.i4002s[0].read_ports().value();
-    let hammering = ram0 & 0b10 == 0b10;
+    let printer_port = self.ram_port(self.wiring.printer_ram);
+    let hammering = printer_port & self.wiring.hammer == self.wiring.hammer;
     if !self.hammering && hammering {
       self.hammering = true;
       return true;
@@ -79,86 +309,154 @@ impl Board {
     self.hammering = hammering;
     false
   }
+  
+  pub fn lamps(&self) -> Lamps {
+    let lamp_port = self.ram_port(self.wiring.lamp_ram);
+    Lamps {
+      memory: lamp_port & self.wiring.memory_lamp == self.wiring.memory_lamp,
+      overflow: lamp_port & self.wiring.overflow_lamp == self.wiring.overflow_lamp,
+      negative: lamp_port & self.wiring.negative_lamp == self.wiring.negative_lamp,
+    }
+  }
 }
 
 
-fn convert_ram_index(command_control: u4, designated_index: i4004::DesignatedIndex) -> usize {
-  let bank = match command_control.value() {
-    0b000 => 0,
-    0b001 => 1,
-    0b010 => 2,
-    0b100 => 3,
-    _ => { warn!("Invalid command control register: {}", command_control);
-      0
-    },
-  };
-  //bank takes upper 2 bits. designated index takes lower 2 bits.
-  (designated_index.chip_index().value() | (bank << 2)) as usize
-}
+/// Each instruction cycle takes 10.8 microseconds.
+/// Keys are held for about 50 milliseconds, so several keyboard scans see them.
+const KEY_HOLD_CYCLES: u32 = 5_000;
+/// About 1.5 seconds, which is enough to print a few lines.
+const KEY_SETTLE_CYCLES: u32 = 140_000;
+
+/// The printer drum takes about 28 milliseconds per sector.
+const CYCLES_PER_SECTOR: u32 = 2_600;
+/// The sector signal is only active for the start of each sector.
+const SECTOR_PULSE_CYCLES: u32 = 650;
+const SECTOR_COUNT: u8 = 13;
+/// 15 digit columns, a blank column, and 2 symbol columns.
+const PAPER_WIDTH: usize = 18;
 
-struct I4004IO<'a> {
-  i4001s: &'a mut [rom::I4001; 5],
-  i4002s: &'a mut [ram::I4002; 2],
+/// Digit column characters, per drum sector.
+const DIGIT_SECTORS: [char; SECTOR_COUNT as usize] = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '.', '.', '-'];
+/// Column 17 characters, per drum sector.
+const SYMBOL_SECTORS: [char; SECTOR_COUNT as usize] = ['◇', '+', '-', '×', '÷', 'M', 'M', '^', '=', '√', '%', 'C', 'R'];
+/// Column 18 characters, per drum sector.
+const SYMBOL2_SECTORS: [char; SECTOR_COUNT as usize] = ['#', '*', 'I', 'Ⅱ', 'Ⅲ', '+', '-', 'T', 'K', 'E', 'Ε', 'C', 'M'];
+
+/// Keys in the order of the keyboard matrix. Each shifter column holds 4 keys, one per ROM 1 input bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+  ClearMemory, RecallMemory, MemoryMinus, MemoryPlus,
+  SquareRoot, Percent, MemoryEqualsMinus, MemoryEqualsPlus,
+  Diamond, Divide, Multiply, Equals,
+  Minus, Plus, Diamond2, TripleZero,
+  Nine, Six, Three, Point,
+  Eight, Five, Two, DoubleZero,
+  Seven, Four, One, Zero,
+  ChangeSign, Exchange, ClearEntry, Clear,
 }
-impl i4004::IO for I4004IO<'_> {
-  fn read_rom_byte(&self, address: i4004::ROMAddress) -> u8 {
-    let high_addr = address.chip_index().value() as usize;
-    let low_addr = address.offset();
-    let i4001 = &self.i4001s[high_addr % self.i4001s.len()];  //Wrap around
-    i4001.read(low_addr)
+
+impl Key {
+  /// Returns (shifter column, ROM 1 input bit)
+  fn matrix_position(self) -> (u8, u8) {
+    let index = self as u8;
+    (index / 4, index % 4)
   }
   
-  fn read_rom_ports(&self, designated_index: i4004::DesignatedIndex) -> u4 {
-    let high_addr = (designated_index.chip_index() << 2 | designated_index.reg_index()).value() as usize;
-    let i4001 = &self.i4001s[high_addr % self.i4001s.len()];  //Wrap around
-    i4001.read_ports()
+  /// Names used by keystroke scripts.
+  pub fn from_name(name: &str) -> Option<Key> {
+    Some(match name {
+      "0" => Key::Zero, "1" => Key::One, "2" => Key::Two, "3" => Key::Three, "4" => Key::Four,
+      "5" => Key::Five, "6" => Key::Six, "7" => Key::Seven, "8" => Key::Eight, "9" => Key::Nine,
+      "00" => Key::DoubleZero, "000" => Key::TripleZero, "." => Key::Point,
+      "+" => Key::Plus, "-" => Key::Minus, "*" | "x" => Key::Multiply, "/" => Key::Divide, "=" => Key::Equals,
+      "%" => Key::Percent, "SQRT" => Key::SquareRoot, "S" => Key::ChangeSign, "EX" => Key::Exchange,
+      "C" => Key::Clear, "CE" => Key::ClearEntry, "#" => Key::Diamond, "##" => Key::Diamond2,
+      "CM" => Key::ClearMemory, "RM" => Key::RecallMemory, "M+" => Key::MemoryPlus, "M-" => Key::MemoryMinus,
+      "M=+" => Key::MemoryEqualsPlus, "M=-" => Key::MemoryEqualsMinus,
+      _ => return None,
+    })
   }
-  fn write_rom_ports(&mut self, designated_index: i4004::DesignatedIndex, value: u4) {
-    let high_addr = (designated_index.chip_index() << 2 | designated_index.reg_index()).value() as usize;
-    let i4001 = &mut self.i4001s[high_addr % self.i4001s.len()];  //Wrap around
-    i4001.write_ports(value);
+}
+
+/// Drives the input port of a ROM chip, if there is such a ROM.
+fn write_rom_port(i4001s: &mut [rom::I4001], chip: usize, value: u8) {
+  if let Some(i4001) = i4001s.get_mut(chip) {
+    i4001.write_ports(u4::new(value & 0xF));
   }
-  
-  fn read_ram_character(&self, command_control: u4, designated_index: i4004::DesignatedIndex) -> u4 {
-    let high_addr = convert_ram_index(command_control, designated_index);
-    if let Some(i4002) = self.i4002s.get(high_addr) {
-      i4002.read_character(designated_index.reg_index(), designated_index.char_index())
-    } else {
-      warn!("Write to nonexisting ram {}.", high_addr);
-      u4::new(0)
+}
+
+/// Printer drum, keyboard and paper, for running without a GUI.
+struct PaperTape {
+  cycle: u32,
+  key: Option<Key>,
+  line: [char; PAPER_WIDTH],
+  lines: Vec<String>,
+  hammering: bool,
+  advance_paper: bool,
+}
+
+impl PaperTape {
+  fn new() -> Self {
+    Self {
+      cycle: 0,
+      key: None,
+      line: [' '; PAPER_WIDTH],
+      lines: vec![],
+      hammering: false,
+      advance_paper: false,
     }
   }
-  fn write_ram_character(&mut self, command_control: u4, designated_index: i4004::DesignatedIndex, value: u4) {
-    let high_addr = convert_ram_index(command_control, designated_index);
-    if let Some(i4002) = self.i4002s.get_mut(high_addr) {
-      i4002.write_character(designated_index.reg_index(), designated_index.char_index(), value);
-    } else {
-      warn!("Write to nonexisting ram {}.", high_addr);
-    }
+  
+  fn sector(&self) -> u8 {
+    (self.cycle / CYCLES_PER_SECTOR) as u8
   }
-  fn read_ram_status(&self, command_control: u4, designated_index: i4004::DesignatedIndex, status_index: u2) -> u4 {
-    let high_addr = convert_ram_index(command_control, designated_index);
-    if let Some(i4002) = self.i4002s.get(high_addr) {
-      i4002.read_status(designated_index.reg_index(), status_index)
-    } else {
-      warn!("Write to nonexisting ram {}.", high_addr);
-      u4::new(0)
+  
+  /// Rotates the drum, and sets up the signals the firmware reads.
+  fn drive_inputs(&mut self, wiring: &Wiring, i4001s: &mut [rom::I4001], keyboard: &shifter::I4003, i4004: &mut cpu::I4004) {
+    self.cycle = (self.cycle + 1) % (CYCLES_PER_SECTOR * SECTOR_COUNT as u32);
+    
+    //TEST pin = sector signal
+    i4004.test = self.cycle % CYCLES_PER_SECTOR < SECTOR_PULSE_CYCLES;
+    
+    //Index signal, when sector 0 comes around.
+    let index = self.sector() == 0 && self.cycle < SECTOR_PULSE_CYCLES;
+    write_rom_port(i4001s, wiring.printer_index_rom, if index { wiring.printer_index } else { 0 });
+    
+    //Keyboard rows, for the column selected by shifter 0.
+    let mut rows = 0;
+    if let Some(key) = self.key {
+      let (column, row) = key.matrix_position();
+      if keyboard.read_parallel() & (1 << column) != 0 {
+        rows |= 1 << row;
+      }
     }
+    write_rom_port(i4001s, wiring.keyboard_rom, rows);
   }
-  fn write_ram_status(&mut self, command_control: u4, designated_index: i4004::DesignatedIndex, status_index: u2, value: u4) {
-    let high_addr = convert_ram_index(command_control, designated_index);
-    if let Some(i4002) = self.i4002s.get_mut(high_addr) {
-      i4002.write_status(designated_index.reg_index(), status_index, value);
-    } else {
-      warn!("Write to nonexisting ram {}.", high_addr);
+  
+  /// Shifter bits 0 and 1 select columns 17 and 18. Bits 3 to 17 select digit columns 15 to 1.
+  fn print(&mut self, wiring: &Wiring, shift_bits: u32, printer_port: u8) {
+    let hammering = printer_port & wiring.hammer == wiring.hammer;
+    if !self.hammering && hammering {
+      let sector = self.sector() as usize;
+      if shift_bits & 0b1 != 0 {
+        self.line[16] = SYMBOL_SECTORS[sector];
+      }
+      if shift_bits & 0b10 != 0 {
+        self.line[17] = SYMBOL2_SECTORS[sector];
+      }
+      for column in 0..15 {
+        if shift_bits & (1 << (17 - column)) != 0 {
+          self.line[column] = DIGIT_SECTORS[sector];
+        }
+      }
     }
-  }
-  fn write_ram_ports(&mut self, command_control: u4, designated_index: i4004::DesignatedIndex, value: u4) {
-    let high_addr = convert_ram_index(command_control, designated_index);
-    if let Some(i4002) = self.i4002s.get_mut(high_addr) {
-      i4002.write_ports(value);
-    } else {
-      warn!("Write to nonexisting ram {}.", high_addr);
+    self.hammering = hammering;
+    
+    let advance_paper = printer_port & wiring.advance_paper == wiring.advance_paper;
+    if !self.advance_paper && advance_paper {
+      self.lines.push(self.line.iter().collect());
+      self.line = [' '; PAPER_WIDTH];
     }
+    self.advance_paper = advance_paper;
   }
-}
\ No newline at end of file
+}
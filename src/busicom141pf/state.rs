@@ -0,0 +1,157 @@
+//! Decodes the calculator's numbers from the 4002 registers, following the register map of the 141-PF firmware,
+//! as named in the annotated firmware listing published at 4004.com: WR, DR, RR and CR in RAM 0, then KR, SR, MR and TR in RAM 1.
+//! Each number register holds 14 BCD digits in characters 0 to 13, least significant digit first.
+//! Status character 0 of every number register, including the keyboard register, holds the number of digits after the decimal point.
+//! Status character 1 is non-zero when negative.
+
+use std::fmt;
+
+use arbitrary_int::{u2,u4};
+
+use super::Board;
+
+/// Number registers, by (RAM chip, register)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+  Working,
+  Dividend,
+  Result,
+  Constant,
+  Keyboard,
+  Subtotal,
+  Memory,
+  Temporary,
+}
+
+impl Register {
+  fn location(self) -> (usize, u2) {
+    let index = self as u8;
+    ((index / 4) as usize, u2::new(index % 4))
+  }
+}
+
+const DIGITS: u8 = 14;
+
+const DECIMALS_STATUS: u2 = u2::new(0);
+const SIGN_STATUS: u2 = u2::new(1);
+/// Keyboard register status character 3 holds the operation waiting for its second operand.
+const OPERATION_STATUS: u2 = u2::new(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Number {
+  pub digits: u64,
+  /// Digits after the decimal point
+  pub decimals: u8,
+  pub negative: bool,
+}
+
+impl Number {
+  pub fn to_f64(&self) -> f64 {
+    let value = self.digits as f64 / 10f64.powi(self.decimals as i32);
+    if self.negative { -value } else { value }
+  }
+}
+
+impl fmt::Display for Number {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let digits = format!("{:0width$}", self.digits, width = self.decimals as usize + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - self.decimals as usize);
+    if self.negative {
+      write!(f, "-")?;
+    }
+    if fraction.is_empty() {
+      write!(f, "{}", integer)
+    } else {
+      write!(f, "{}.{}", integer, fraction)
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+}
+
+impl Operation {
+  fn from_code(code: u4) -> Option<Self> {
+    match code.value() {
+      1 => Some(Operation::Add),
+      2 => Some(Operation::Subtract),
+      3 => Some(Operation::Multiply),
+      4 => Some(Operation::Divide),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+  pub working: Number,
+  pub memory: Number,
+  pub subtotal: Number,
+  pub constant: Number,
+  pub pending_operation: Option<Operation>,
+  /// The number being typed, which holds the digits after the decimal point typed so far.
+  pub keyboard: Number,
+}
+
+impl Board {
+  /// Reads a number register. Returns zero if its RAM chip is not populated.
+  pub fn read_register(&self, register: Register) -> Number {
+    let (chip, reg_index) = register.location();
+    let i4002 = match self.i4002s.get(chip) {
+      Some(i4002) => i4002,
+      None => return Number::default(),
+    };
+    let mut digits = 0;
+    for char_index in (0..DIGITS).rev() {
+      let digit = i4002.read_character(reg_index, u4::new(char_index)).value();
+      digits = digits * 10 + digit.min(9) as u64;
+    }
+    Number {
+      digits,
+      decimals: i4002.read_status(reg_index, DECIMALS_STATUS).value(),
+      negative: i4002.read_status(reg_index, SIGN_STATUS).value() != 0,
+    }
+  }
+  
+  pub fn state(&self) -> State {
+    let (chip, reg_index) = Register::Keyboard.location();
+    let operation = self.i4002s.get(chip).and_then(|i4002| Operation::from_code(i4002.read_status(reg_index, OPERATION_STATUS)));
+    State {
+      working: self.read_register(Register::Working),
+      memory: self.read_register(Register::Memory),
+      subtotal: self.read_register(Register::Subtotal),
+      constant: self.read_register(Register::Constant),
+      pending_operation: operation,
+      keyboard: self.read_register(Register::Keyboard),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn number(digits: u64, decimals: u8, negative: bool) -> Number {
+    Number { digits, decimals, negative }
+  }
+
+  #[test]
+  fn display() {
+    assert_eq!(number(46, 0, false).to_string(), "46");
+    assert_eq!(number(1234, 2, false).to_string(), "12.34");
+    assert_eq!(number(5, 3, true).to_string(), "-0.005");
+    assert_eq!(number(0, 0, false).to_string(), "0");
+  }
+
+  #[test]
+  fn to_f64() {
+    assert_eq!(number(46, 0, false).to_f64(), 46.0);
+    assert_eq!(number(1234, 2, true).to_f64(), -12.34);
+    assert_eq!(number(5, 3, false).to_f64(), 0.005);
+  }
+}
@@ -1,64 +1,423 @@
-//! HP Classic Calculators includes HP-35 and HP-45.
+//! HP Classic Calculators includes HP-35, HP-45, HP-55, HP-70 and HP-80.
+//! The HP-65 is not supported yet. See the README.
 //! Each clock cycle ends up taking 280 microseconds. (3.671 kHz)
+//! The HP-45 timer mode (RCL CHS) counts word cycles, so it only keeps time when run at authentic speed with `run_for`.
+//! The board spots the running stopwatch from the display, and then ignores turbo. So does the HP-55 with its mode switch on TIMER.
+
+use std::time::Duration;
 
 const ROM_CHIP_LEN: usize = 320;  /// 256 * 10 bits = 2560 bits of ROM data. 2560 / 8 = 320 bytes
 use chips::{rom,cpu,ram};
 use arbitrary_int::{
   u3,   //ROM #
   u10,  //ROM opcode
+  u12,  //Status bits
   u14,  //Word Select
 };
 
 use chips::shifter;
 type WordSelect = shifter::Shifter16<14>;
 
+pub mod keyboard;
+use keyboard::HeldKey;
+use log::warn;
+pub use keyboard::{Key, KeyCode, HP35Key, HP45Key, HP55Key, HP70Key, HP80Key};
+pub mod display;
+pub use display::Display;
+pub mod trace;
+pub use trace::TraceRecord;
+pub mod rom_image;
+pub use rom_image::RomImageError;
+pub mod model;
+pub use model::Model;
+pub mod memory;
+pub mod disassembler;
+pub mod assembler;
+
+const CYCLE_TIME: Duration = Duration::from_micros(280);
+/// How many times faster than the real calculator turbo mode runs.
+const TURBO_FACTOR: u64 = 20;
+
+/// SELECT ROM n = n 0010000
+const SELECT_ROM: u16 = 0b0010000;
+/// DELAYED SELECT ROM n = n 1110100
+const DELAYED_SELECT_ROM: u16 = 0b1110100;
+
+/// DELAYED SELECT GROUP n = 10 n 0110100
+const DELAYED_SELECT_GROUP_MASK: u16 = 0b1101111111;
+const DELAYED_SELECT_GROUP: u16 = 0b1000110100;
+/// ROM numbers only go up to 7. Models with more ROMs switch between groups of 8.
+const GROUP_LEN: usize = 8;
+
+/// The HP-55 mode switch drives C&T status inputs, the way a held key drives status bit 0.
+/// These bit numbers have not been checked against the HP-55 ROMs.
+const PROGRAM_SWITCH_STATUS: u16 = 1 << 3;
+const TIMER_SWITCH_STATUS: u16 = 1 << 11;
+
+/// Position of the HP-55 RUN / TIMER / PRGM slide switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModeSwitch {
+  #[default]
+  Run,
+  Timer,
+  Program,
+}
+
+/// The firmware blanks the display while it computes, and leaves it alone while it waits for a key.
+/// A lit display which keeps changing with no key pressed is the running stopwatch.
+/// A single change after a key press is the firmware showing its result, so several are needed.
+const STOPWATCH_CHANGES: u32 = 3;
+/// The firmware changes A and B on consecutive cycles while it works on them, but the stopwatch
+/// changes at most once per hundredth of a second, about 36 cycles. Only changes this far apart are counted.
+const STOPWATCH_MIN_GAP: u32 = 16;
+/// The seconds change at least this often while the stopwatch runs. (One second)
+const STOPWATCH_CYCLES: u32 = 3571;
+
 pub struct Board<const EXTRA_REGS: usize> {
   pub anr: cpu::HP_AnR,
   pub cnt: cpu::HP_CnT,
   pub roms: Vec<rom::HP_ROM>,
   pub ram: ram::HP_RAM<EXTRA_REGS>,
+  key: HeldKey,
+  trace: Option<Box<dyn FnMut(&TraceRecord) + Send>>,
+  /// ROM picked by DELAYED SELECT ROM, waiting for the next jump.
+  delayed_rom: Option<u3>,
+  /// ROM group picked by DELAYED SELECT GROUP, waiting for the next jump.
+  delayed_group: Option<usize>,
+  /// Only the ROMs of this group respond.
+  rom_group: usize,
+  /// Runs `run_for` faster than the real calculator. It is ignored while the stopwatch runs, or the mode switch is on TIMER, so the stopwatch keeps real time.
+  pub turbo: bool,
+  has_mode_switch: bool,
+  mode_switch: ModeSwitch,
+  /// The firmware has a stopwatch.
+  has_timer: bool,
+  /// A and B as last seen, for spotting the running stopwatch.
+  last_display: (u64, u64),
+  /// Changes of the lit display since the last key press, apart from quick successions.
+  idle_display_changes: u32,
+  cycles_since_display_change: u32,
+  /// Time left over from the last `run_for`, which was too short for a whole cycle.
+  unused_time: Duration,
+  powered: bool,
+  /// Battery voltage is low. The display driver shows it by lighting every decimal point.
+  /// The classic chips have no battery sense input, so the firmware cannot see it, and keeps running normally.
+  pub low_battery: bool,
+}
+
+/// The HP-35 has no data storage chip.
+pub type HP35 = Board<0>;
+/// The HP-45 has a 10 register data storage chip.
+pub type HP45 = Board<10>;
+/// The HP-55 has a 30 register data storage chip, which also holds its program.
+pub type HP55 = Board<30>;
+/// The HP-70 has a 10 register data storage chip.
+pub type HP70 = Board<10>;
+/// The HP-80 has a 10 register data storage chip.
+pub type HP80 = Board<10>;
+
+/// Creates an HP-35 from ".obj" ROM text.
+pub fn hp35(obj: &str) -> Result<HP35, RomImageError> {
+  Board::new_model(&model::HP35, obj)
+}
+
+/// Creates an HP-45 from ".obj" ROM text.
+pub fn hp45(obj: &str) -> Result<HP45, RomImageError> {
+  Board::new_model(&model::HP45, obj)
+}
+
+/// Creates an HP-55 from ".obj" ROM text.
+pub fn hp55(obj: &str) -> Result<HP55, RomImageError> {
+  Board::new_model(&model::HP55, obj)
+}
+
+/// Creates an HP-70 from ".obj" ROM text.
+pub fn hp70(obj: &str) -> Result<HP70, RomImageError> {
+  Board::new_model(&model::HP70, obj)
+}
+
+/// Creates an HP-80 from ".obj" ROM text.
+pub fn hp80(obj: &str) -> Result<HP80, RomImageError> {
+  Board::new_model(&model::HP80, obj)
+}
+
+/// Running a number of cycles.
+pub trait RunCycles {
+  fn run_cycle(&mut self);
+  
+  fn run_cycles(&mut self, cycles: u32) {
+    for _ in 0..cycles {
+      self.run_cycle();
+    }
+  }
+}
+
+impl<const EXTRA_REGS: usize> RunCycles for Board<EXTRA_REGS> {
+  fn run_cycle(&mut self) {
+    Board::run_cycle(self);
+  }
 }
 
 impl<const EXTRA_REGS: usize> Board<EXTRA_REGS> {
+  /// Creates a board from ".obj" ROM text, warning if the ROMs or registers do not match the model.
+  /// Fails on lines which are not octal opcodes.
+  pub fn new_model(model: &Model, obj: &str) -> Result<Self, RomImageError> {
+    let opcodes = rom_image::parse_model(model.name, model.rom_count * rom_image::ROM_WORDS, model.data_registers, EXTRA_REGS, obj)?;
+    let mut board = Self::new(rom_image::pack(&opcodes));
+    board.has_timer = model.timer;
+    board.has_mode_switch = model.mode_switch;
+    Ok(board)
+  }
+  
+  /// ROM data is packed 10 bits per opcode, 320 bytes per ROM. See `rom_image` for converting other formats.
   pub fn new(packed_rom_data: Vec<u8>) -> Self {
     let mut roms = vec![];
-    let mut rom_num = u3::new(0);
-    for chunk in packed_rom_data.chunks(ROM_CHIP_LEN) {
+    for (index, chunk) in packed_rom_data.chunks(ROM_CHIP_LEN).enumerate() {
       let mut padded_chunk = Vec::from(chunk);
       padded_chunk.resize_with(ROM_CHIP_LEN, Default::default); //This is needed for the last chunk if it is less than the total.
+      let rom_num = u3::new((index % GROUP_LEN) as u8);
       roms.push(rom::HP_ROM::new(padded_chunk.try_into().unwrap(), rom_num));
-      rom_num += u3::new(1);
     }
     Self {
       anr: cpu::HP_AnR::new(),
       cnt: cpu::HP_CnT::new(),
       roms,
       ram: ram::HP_RAM::new(),
+      key: HeldKey::default(),
+      trace: None,
+      delayed_rom: None,
+      delayed_group: None,
+      rom_group: 0,
+      turbo: false,
+      has_timer: false,
+      has_mode_switch: false,
+      mode_switch: ModeSwitch::Run,
+      last_display: (0, 0),
+      idle_display_changes: 0,
+      cycles_since_display_change: 0,
+      unused_time: Duration::ZERO,
+      powered: true,
+      low_battery: false,
+    }
+  }
+  
+  /// The power on reset sequence. The chips are cleared, ROM 0 of group 0 is selected, and execution starts from address 0.
+  /// The data storage chip is not continuous memory, so it is cleared as well.
+  pub fn power_on(&mut self) {
+    self.anr = cpu::HP_AnR::new();
+    self.cnt = cpu::HP_CnT::new();
+    self.ram = ram::HP_RAM::new();
+    self.rom_group = 0;
+    self.delayed_rom = None;
+    self.delayed_group = None;
+    let select_rom0 = u10::new(SELECT_ROM);
+    for rom in &mut self.roms {
+      rom.decode(select_rom0);
+    }
+    self.key.release();
+    self.idle_display_changes = 0;
+    self.unused_time = Duration::ZERO;
+    self.powered = true;
+  }
+  
+  /// Stops running cycles, and blanks the display. Use `power_on` to start again.
+  pub fn power_off(&mut self) {
+    self.powered = false;
+    self.key.release();
+  }
+  
+  pub fn powered(&self) -> bool {
+    self.powered
+  }
+  
+  /// Calls `trace` after every cycle. Use `trace::log_trace` to send records to the log.
+  pub fn set_trace(&mut self, trace: impl FnMut(&TraceRecord) + Send + 'static) {
+    self.trace = Some(Box::new(trace));
+  }
+  
+  pub fn clear_trace(&mut self) {
+    self.trace = None;
+  }
+  
+  /// Holds the key down for a short while, then releases it.
+  pub fn press_key(&mut self, key: impl Key) {
+    self.key.press(key.key_code());
+    self.idle_display_changes = 0;
+  }
+  
+  /// Follows the display, to spot the running stopwatch.
+  fn watch_display(&mut self) {
+    let display = (self.anr.a.value(), self.anr.b.value());
+    if !self.anr.display_on || self.key.is_down() {
+      self.idle_display_changes = 0;
+    } else if display != self.last_display {
+      if self.cycles_since_display_change >= STOPWATCH_MIN_GAP {
+        self.idle_display_changes = self.idle_display_changes.saturating_add(1);
+      }
+      self.cycles_since_display_change = 0;
+    }
+    self.cycles_since_display_change = self.cycles_since_display_change.saturating_add(1);
+    self.last_display = display;
+  }
+  
+  /// The HP-45 or HP-55 stopwatch is running. Models without a stopwatch never run one.
+  pub fn timer_running(&self) -> bool {
+    self.has_timer && self.idle_display_changes >= STOPWATCH_CHANGES && self.cycles_since_display_change <= STOPWATCH_CYCLES
+  }
+  
+  /// Slides the HP-55 mode switch. The firmware reads it like it reads the keys, so it stays where it is across `power_on`.
+  pub fn set_mode_switch(&mut self, mode_switch: ModeSwitch) {
+    if !self.has_mode_switch {
+      warn!("This model has no mode switch.");
+      return;
+    }
+    self.mode_switch = mode_switch;
+  }
+  
+  pub fn mode_switch(&self) -> ModeSwitch {
+    self.mode_switch
+  }
+  
+  /// Turbo would make the stopwatch run fast.
+  fn keeps_real_time(&self) -> bool {
+    self.timer_running() || self.mode_switch == ModeSwitch::Timer
+  }
+  
+  pub fn key_pressed(&self) -> bool {
+    self.key.is_down()
+  }
+  
+  pub fn display(&self) -> Display {
+    let display = Display::decode(self.anr.a.value(), self.anr.b.value(), self.powered && self.anr.display_on);
+    if self.low_battery { display.low_battery() } else { display }
+  }
+  
+  /// Saves the data storage registers, such as the HP-45's STO registers.
+  pub fn save_memory(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+    memory::save(&self.ram, path)
+  }
+  
+  pub fn load_memory(&mut self, path: impl AsRef<std::path::Path>) -> Result<(), memory::MemoryError> {
+    memory::load(&mut self.ram, path)
+  }
+  
+  /// While a key is down, the keyboard keeps the key code on the C&T and keeps setting status bit 0.
+  /// The mode switch keeps setting the status bit of its position.
+  fn scan_keyboard(&mut self) {
+    if let Some(key_code) = self.key.scan() {
+      self.cnt.key_code = key_code;
+      self.cnt.status |= u12::new(1);
+    }
+    match self.mode_switch {
+      ModeSwitch::Run => {},
+      ModeSwitch::Timer => self.cnt.status |= u12::new(TIMER_SWITCH_STATUS),
+      ModeSwitch::Program => self.cnt.status |= u12::new(PROGRAM_SWITCH_STATUS),
+    }
+  }
+
+  /// Runs as many cycles as the real calculator would in this much wall clock time. Returns the number of cycles run.
+  /// Call it with the time elapsed since the last call, to run at authentic speed.
+  /// Nothing runs while the power is off.
+  /// Very long durations, such as the time across a host sleep, saturate instead of overflowing.
+  pub fn run_for(&mut self, duration: Duration) -> u64 {
+    if !self.powered {
+      return 0;
+    }
+    let time = self.unused_time.saturating_add(duration);
+    let mut cycles = u64::try_from(time.as_nanos() / CYCLE_TIME.as_nanos()).unwrap_or(u64::MAX);
+    self.unused_time = Duration::from_nanos((time.as_nanos() % CYCLE_TIME.as_nanos()) as u64);
+    if self.turbo && !self.keeps_real_time() {
+      cycles = cycles.saturating_mul(TURBO_FACTOR);
+    }
+    for _ in 0..cycles {
+      self.run_cycle();
+    }
+    cycles
+  }
+  
+  /// Runs until the display shows something else, up to `max_cycles`. Returns the new display, or None if it did not change.
+  pub fn run_until_display_change(&mut self, max_cycles: u32) -> Option<Display> {
+    let display = self.display();
+    for _ in 0..max_cycles {
+      self.run_cycle();
+      let new_display = self.display();
+      if new_display != display {
+        return Some(new_display);
+      }
+    }
+    None
+  }
+
+  /// ROMs of the selected group.
+  fn group_roms(&mut self) -> impl Iterator<Item = &mut rom::HP_ROM> + '_ {
+    self.roms.iter_mut().skip(self.rom_group * GROUP_LEN).take(GROUP_LEN)
+  }
+  
+  /// DELAYED SELECT ROM (HP-45 and later) and DELAYED SELECT GROUP (HP-55 and HP-65) switch ROMs on the next JSB, or the next GO TO that is taken.
+  /// The ROMs only know the immediate SELECT ROM, so it is handed to them at the time of the jump.
+  fn delayed_select_rom(&mut self, opcode: u10) {
+    let opcode = opcode.value();
+    let jsb = opcode & 0b11 == 0b01;
+    let go_to_taken = opcode & 0b11 == 0b11 && !self.anr.next_carry;
+    if jsb || go_to_taken {
+      if let Some(group) = self.delayed_group.take() {
+        self.rom_group = group;
+      }
+      if let Some(rom_num) = self.delayed_rom.take() {
+        let select_rom = u10::new((rom_num.value() as u16) << 7 | SELECT_ROM);
+        for rom in self.group_roms() {
+          rom.decode(select_rom);
+        }
+      }
+    }
+    if opcode & 0b1111111 == DELAYED_SELECT_ROM {
+      self.delayed_rom = Some(u3::new((opcode >> 7) as u8));
+    }
+    if opcode & DELAYED_SELECT_GROUP_MASK == DELAYED_SELECT_GROUP {
+      self.delayed_group = Some((opcode as usize >> 7) & 0b1);
     }
   }
 
   pub fn run_cycle(&mut self) {
+    if !self.powered {
+      return;
+    }
+    self.scan_keyboard();
+    
+    let address = self.cnt.next_address;
     let mut opcode = u10::new(0);
     let mut word_select_data = 0;
-    for rom in &mut self.roms {
-      let (opcode_rom, word_select_data_rom) = rom.read(self.cnt.next_address);
+    for rom in self.group_roms() {
+      let (opcode_rom, word_select_data_rom) = rom.read(address);
       opcode |= opcode_rom;
       word_select_data |= word_select_data_rom.read_parallel();
     }
     
-    //ROM SELECT Decoding done on all ROMS.
-    for rom in &mut self.roms {
+    //ROM SELECT Decoding done on all ROMS of the group.
+    for rom in self.group_roms() {
       rom.decode(opcode);
     }
+    self.delayed_select_rom(opcode);
     
     //Run C&T and A&R
     word_select_data |= self.cnt.run_cycle(opcode, self.anr.next_carry).read_parallel();
     let ram_data = self.ram.run_cycle(opcode, self.anr.c);
     self.anr.run_cycle(opcode, WordSelect::new(word_select_data), ram_data);
+    if self.has_timer {
+      self.watch_display();
+    }
     
-    self.cnt.print();
-    self.anr.print();
-    
+    if let Some(trace) = &mut self.trace {
+      trace(&TraceRecord {
+        address,
+        opcode,
+        a: self.anr.a.value(),
+        b: self.anr.b.value(),
+        c: self.anr.c.value(),
+        status: self.cnt.status,
+        carry: self.anr.next_carry,
+      });
+    }
   }
 
 }
@@ -0,0 +1,248 @@
+//! Assembles classic mnemonics, as printed by the disassembler, into opcodes for `Board::new`.
+//!
+//! Each line holds an optional "LABEL:" and an optional instruction. Anything after ';' is a comment.
+//! Spaces inside instructions and labels are ignored, and everything is case insensitive, so "a + c -> c[m]" works too.
+//! * `ROM n` starts ROM n, at its address 0. Running past the end of a ROM is an error.
+//! * `ORG nnn` moves to the octal address nnn within the current ROM.
+//! * JSB and GO TO take a label or an octal address. The label must be in the ROM the jump lands in:
+//!   the current ROM, or the one picked by DELAYED SELECT ROM and DELAYED SELECT GROUP since the last jump.
+//!
+//! Unused words are filled with NO OPERATION, up to the end of the last ROM.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use arbitrary_int::u10;
+
+use super::disassembler::{ARITHMETIC, FIELDS, FIXED};
+use super::rom_image::{self, ROM_WORDS};
+use super::{DELAYED_SELECT_ROM, DELAYED_SELECT_GROUP, DELAYED_SELECT_GROUP_MASK, GROUP_LEN};
+
+const MAX_ROMS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblerError {
+  UnknownInstruction { line: usize, instruction: String },
+  UnknownLabel { line: usize, label: String },
+  DuplicateLabel { line: usize, label: String },
+  /// The jump target is not in the ROM the jump lands in.
+  OtherRom { line: usize, label: String },
+  /// Number is too big for the instruction, or the address is past the end of the ROM.
+  OutOfRange { line: usize },
+}
+
+impl fmt::Display for AssemblerError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      AssemblerError::UnknownInstruction { line, instruction } => write!(f, "Line {}: Unknown instruction {}", line, instruction),
+      AssemblerError::UnknownLabel { line, label } => write!(f, "Line {}: Unknown label {}", line, label),
+      AssemblerError::DuplicateLabel { line, label } => write!(f, "Line {}: Label {} is already defined", line, label),
+      AssemblerError::OtherRom { line, label } => write!(f, "Line {}: Label {} is in another ROM", line, label),
+      AssemblerError::OutOfRange { line } => write!(f, "Line {}: Out of range", line),
+    }
+  }
+}
+
+impl std::error::Error for AssemblerError {}
+
+/// A source line, split into its parts.
+struct Line {
+  number: usize,
+  label: Option<String>,
+  /// Upper case, without spaces.
+  instruction: Option<String>,
+}
+
+fn parse_line(number: usize, text: &str) -> Line {
+  let text = text.split(';').next().unwrap_or_default();
+  let (label, instruction) = match text.split_once(':') {
+    Some((label, instruction)) => (Some(compact(label)), instruction),
+    None => (None, text),
+  };
+  let instruction = compact(instruction);
+  Line {
+    number,
+    label,
+    instruction: (!instruction.is_empty()).then_some(instruction),
+  }
+}
+
+/// Instruction or label without spaces, for matching.
+fn compact(mnemonic: &str) -> String {
+  mnemonic.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase()
+}
+
+fn parse_number(text: &str, radix: u32, max: u16, line: usize) -> Result<u16, AssemblerError> {
+  u16::from_str_radix(text, radix).ok().filter(|&number| number <= max).ok_or(AssemblerError::OutOfRange { line })
+}
+
+/// Directives change the address instead of emitting an opcode.
+fn directive(instruction: &str, rom: usize, line: usize) -> Result<Option<usize>, AssemblerError> {
+  if let Some(rom) = instruction.strip_prefix("ROM") {
+    let rom = parse_number(rom, 10, MAX_ROMS as u16 - 1, line)?;
+    return Ok(Some(rom as usize * ROM_WORDS));
+  }
+  if let Some(offset) = instruction.strip_prefix("ORG") {
+    let offset = parse_number(offset, 8, ROM_WORDS as u16 - 1, line)?;
+    return Ok(Some(rom * ROM_WORDS + offset as usize));
+  }
+  Ok(None)
+}
+
+/// Resolves a jump target to its 8 bit address within `target_rom`.
+fn jump_target(target: &str, target_rom: usize, labels: &HashMap<String, usize>, line: usize) -> Result<u16, AssemblerError> {
+  if let Some(&label_address) = labels.get(target) {
+    if label_address / ROM_WORDS != target_rom {
+      return Err(AssemblerError::OtherRom { line, label: target.to_string() });
+    }
+    return Ok((label_address % ROM_WORDS) as u16);
+  }
+  if target.chars().all(|c| c.is_digit(8)) {
+    return parse_number(target, 8, ROM_WORDS as u16 - 1, line);
+  }
+  Err(AssemblerError::UnknownLabel { line, label: target.to_string() })
+}
+
+/// Instructions of the form "prefix n suffix", where n is decimal.
+fn numbered(instruction: &str, prefix: &str, suffix: &str, max: u16, line: usize) -> Option<Result<u16, AssemblerError>> {
+  let number = instruction.strip_prefix(prefix)?.strip_suffix(suffix)?;
+  if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+  Some(parse_number(number, 10, max, line))
+}
+
+fn encode(instruction: &str, target_rom: usize, labels: &HashMap<String, usize>, line: usize) -> Result<u16, AssemblerError> {
+  if let Some(target) = instruction.strip_prefix("JSB") {
+    return Ok(jump_target(target, target_rom, labels, line)? << 2 | 0b01);
+  }
+  if let Some(target) = instruction.strip_prefix("IFNOCARRYGOTO").or_else(|| instruction.strip_prefix("GOTO")) {
+    return Ok(jump_target(target, target_rom, labels, line)? << 2 | 0b11);
+  }
+  for (operation, template) in ARITHMETIC.iter().enumerate() {
+    for (field, name) in FIELDS.iter().enumerate() {
+      if compact(&template.replace('f', name)) == instruction {
+        return Ok((operation as u16) << 5 | (field as u16) << 2 | 0b10);
+      }
+    }
+  }
+  if let Some((opcode, _)) = FIXED.iter().find(|(_, mnemonic)| compact(mnemonic) == instruction) {
+    return Ok(*opcode);
+  }
+  //(prefix, suffix, largest n, opcode bits below n, position of n)
+  let patterns: [(&str, &str, u16, u16, u16); 9] = [
+    ("1->S", "", 15, 0b000100, 6),
+    ("IFS", "=0", 15, 0b010100, 6),
+    ("0->S", "", 15, 0b100100, 6),
+    ("", "->P", 15, 0b001100, 6),
+    ("IFP#", "", 15, 0b101100, 6),
+    ("LOADCONSTANT", "", 15, 0b011000, 6),
+    ("SELECTROM", "", 7, 0b0010000, 7),
+    ("DELAYEDSELECTROM", "", 7, 0b1110100, 7),
+    ("DELAYEDSELECTGROUP", "", 1, 0b1000110100, 7),
+  ];
+  for (prefix, suffix, max, bits, shift) in patterns {
+    if let Some(number) = numbered(instruction, prefix, suffix, max, line) {
+      return Ok(number? << shift | bits);
+    }
+  }
+  Err(AssemblerError::UnknownInstruction { line, instruction: instruction.to_string() })
+}
+
+/// Returns the opcodes of every ROM up to the last one used.
+pub fn assemble(source: &str) -> Result<Vec<u10>, AssemblerError> {
+  let lines: Vec<Line> = source.lines().enumerate().map(|(index, text)| parse_line(index + 1, text)).collect();
+
+  //First pass finds the label addresses.
+  let mut labels = HashMap::new();
+  let mut address = 0;
+  let mut rom = 0;
+  for line in &lines {
+    if let Some(label) = &line.label {
+      if labels.insert(label.clone(), address).is_some() {
+        return Err(AssemblerError::DuplicateLabel { line: line.number, label: label.clone() });
+      }
+    }
+    if let Some(instruction) = &line.instruction {
+      match directive(instruction, rom, line.number)? {
+        Some(new_address) => {
+          address = new_address;
+          rom = address / ROM_WORDS;
+        },
+        None => address += 1,
+      }
+    }
+  }
+
+  //Second pass encodes the instructions.
+  let mut opcodes = vec![];
+  let mut address = 0;
+  let mut rom = 0;
+  //ROM number and group picked by DELAYED SELECT ROM and DELAYED SELECT GROUP, for the next jump.
+  let mut delayed_rom = None;
+  let mut delayed_group = None;
+  for line in &lines {
+    let instruction = match &line.instruction {
+      Some(instruction) => instruction,
+      None => continue,
+    };
+    if let Some(new_address) = directive(instruction, rom, line.number)? {
+      address = new_address;
+      rom = address / ROM_WORDS;
+      continue;
+    }
+    if address >= (rom + 1) * ROM_WORDS {
+      return Err(AssemblerError::OutOfRange { line: line.number });
+    }
+    let group = delayed_group.unwrap_or(rom / GROUP_LEN);
+    let target_rom = group * GROUP_LEN + delayed_rom.unwrap_or(rom % GROUP_LEN);
+    let opcode = encode(instruction, target_rom, &labels, line.number)?;
+    if opcode & 0b11 == 0b01 || opcode & 0b11 == 0b11 {
+      delayed_rom = None;
+      delayed_group = None;
+    } else if opcode & 0b1111111 == DELAYED_SELECT_ROM {
+      delayed_rom = Some(opcode as usize >> 7);
+    } else if opcode & DELAYED_SELECT_GROUP_MASK == DELAYED_SELECT_GROUP {
+      delayed_group = Some((opcode as usize >> 7) & 0b1);
+    }
+    if opcodes.len() <= address {
+      opcodes.resize(address + 1, u10::new(0));
+    }
+    opcodes[address] = u10::new(opcode);
+    address += 1;
+  }
+  opcodes.resize(opcodes.len().div_ceil(ROM_WORDS).max(1) * ROM_WORDS, u10::new(0));
+  Ok(opcodes)
+}
+
+/// Assembles straight into packed ROM data for `Board::new`.
+pub fn assemble_packed(source: &str) -> Result<Vec<u8>, AssemblerError> {
+  Ok(rom_image::pack(&assemble(source)?))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn labels_with_spaces() {
+    let opcodes = assemble("my label: return\n go to My Label").unwrap();
+    assert_eq!(opcodes[1], u10::new(0o0003));
+  }
+
+  #[test]
+  fn rom_overflow() {
+    assert_eq!(assemble("org 377\nreturn\nreturn"), Err(AssemblerError::OutOfRange { line: 3 }));
+    assert_eq!(assemble("org 377\nreturn\nrom 1\nreturn").unwrap().len(), 2 * ROM_WORDS);
+  }
+
+  #[test]
+  fn delayed_select() {
+    let source = "delayed select rom 1\njsb far\njsb far\nrom 1\norg 10\nfar: return";
+    assert_eq!(assemble(source), Err(AssemblerError::OtherRom { line: 3, label: "FAR".to_string() }));
+    let opcodes = assemble("delayed select rom 1\njsb far\nrom 1\norg 10\nfar: return").unwrap();
+    assert_eq!(opcodes[1], u10::new(0o0041));
+    let source = "delayed select group 1\ndelayed select rom 2\ngo to far\nrom 10\nfar: return";
+    assert_eq!(assemble(source).unwrap()[2], u10::new(0o0003));
+  }
+}
@@ -0,0 +1,140 @@
+//! Disassembles classic opcodes into the mnemonics of the HP documentation, such as "A + C -> C[M]" and "IF NO CARRY GO TO 123".
+//! Addresses and numbers are in octal, like the HP listings.
+//!
+//! The lowest 2 bits give the instruction type:
+//! * 01 = JSB, with an 8 bit address
+//! * 11 = IF NO CARRY GO TO, with an 8 bit address
+//! * 10 = Arithmetic, with a 5 bit operation and a 3 bit field
+//! * 00 = Everything else, grouped by bits 2 to 5
+
+use arbitrary_int::u10;
+
+use super::rom_image::ROM_WORDS;
+
+/// Field selects of arithmetic instructions.
+pub const FIELDS: [&str; 8] = ["P", "M", "X", "W", "WP", "MS", "XS", "S"];
+
+/// Arithmetic operations. "f" is replaced with the field.
+pub const ARITHMETIC: [&str; 32] = [
+  "IF B[f] = 0",
+  "0 -> B[f]",
+  "IF A >= C[f]",
+  "IF C[f] >= 1",
+  "B -> C[f]",
+  "0 - C -> C[f]",
+  "0 -> C[f]",
+  "0 - C - 1 -> C[f]",
+  "SHIFT LEFT A[f]",
+  "A -> B[f]",
+  "A - C -> C[f]",
+  "C - 1 -> C[f]",
+  "C -> A[f]",
+  "IF C[f] = 0",
+  "A + C -> C[f]",
+  "C + 1 -> C[f]",
+  "IF A >= B[f]",
+  "B EXCHANGE C[f]",
+  "SHIFT RIGHT C[f]",
+  "IF A[f] >= 1",
+  "SHIFT RIGHT B[f]",
+  "C + C -> C[f]",
+  "SHIFT RIGHT A[f]",
+  "0 -> A[f]",
+  "A - B -> A[f]",
+  "A EXCHANGE B[f]",
+  "A - C -> A[f]",
+  "A - 1 -> A[f]",
+  "A + B -> A[f]",
+  "A EXCHANGE C[f]",
+  "A + C -> A[f]",
+  "A + 1 -> A[f]",
+];
+
+/// Instructions without operands, by opcode.
+pub const FIXED: [(u16, &str); 17] = [
+  (0o0000, "NO OPERATION"),
+  (0o0064, "CLEAR STATUS"),
+  (0o0034, "P - 1 -> P"),
+  (0o0074, "P + 1 -> P"),
+  (0o0060, "RETURN"),
+  (0o0320, "KEYS -> ROM ADDRESS"),
+  (0o0050, "DISPLAY TOGGLE"),
+  (0o0250, "C EXCHANGE M"),
+  (0o0450, "C -> STACK"),
+  (0o0650, "STACK -> A"),
+  (0o1050, "DISPLAY OFF"),
+  (0o1250, "M -> C"),
+  (0o1450, "DOWN ROTATE"),
+  (0o1650, "CLEAR REGISTERS"),
+  (0o1160, "C -> DATA ADDRESS"),
+  (0o1360, "C -> DATA"),
+  (0o1370, "DATA -> C"),
+];
+
+/// Returns the mnemonic for a single opcode.
+pub fn disassemble(opcode: u10) -> String {
+  let opcode = opcode.value();
+  let high = opcode >> 6;  //n of the type 00 instructions
+  match opcode & 0b11 {
+    0b01 => return format!("JSB {:03o}", opcode >> 2),
+    0b11 => return format!("IF NO CARRY GO TO {:03o}", opcode >> 2),
+    0b10 => return ARITHMETIC[(opcode >> 5) as usize].replace('f', FIELDS[((opcode >> 2) & 0b111) as usize]),
+    _ => {},
+  }
+  if let Some((_, mnemonic)) = FIXED.iter().find(|(fixed, _)| *fixed == opcode) {
+    return mnemonic.to_string();
+  }
+  match opcode & 0b111111 {
+    0b000100 => format!("1 -> S{}", high),
+    0b010100 => format!("IF S{} = 0", high),
+    0b100100 => format!("0 -> S{}", high),
+    0b001100 => format!("{} -> P", high),
+    0b101100 => format!("IF P # {}", high),
+    0b011000 => format!("LOAD CONSTANT {}", high),
+    _ => match opcode & 0b1111111 {
+      0b0010000 => format!("SELECT ROM {}", opcode >> 7),
+      0b1110100 => format!("DELAYED SELECT ROM {}", opcode >> 7),
+      0b0110100 if opcode >> 8 == 0b10 => format!("DELAYED SELECT GROUP {}", (opcode >> 7) & 0b1),
+      _ => format!("UNKNOWN {:04o}", opcode),
+    },
+  }
+}
+
+/// One line per opcode, as "ROM:ADDRESS: OPCODE  MNEMONIC", with the ROM number and address in octal.
+pub fn disassemble_rom(opcodes: &[u10]) -> String {
+  let mut listing = String::new();
+  for (index, &opcode) in opcodes.iter().enumerate() {
+    listing += &format!("{:o}:{:03o}: {:04o}  {}\n", index / ROM_WORDS, index % ROM_WORDS, opcode.value(), disassemble(opcode));
+  }
+  listing
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hp_classic::assembler::assemble;
+
+  #[test]
+  fn assembler_round_trip() {
+    let mut known = 0;
+    for opcode in 0..=u10::MAX.value() {
+      let mnemonic = disassemble(u10::new(opcode));
+      if mnemonic.starts_with("UNKNOWN") {
+        continue;
+      }
+      known += 1;
+      assert_eq!(assemble(&mnemonic).map(|opcodes| opcodes[0]), Ok(u10::new(opcode)), "{}", mnemonic);
+    }
+    assert_eq!(known, 899);
+  }
+
+  #[test]
+  fn listing() {
+    let listing = disassemble_rom(&[u10::new(0o1650), u10::new(0o0071)]);
+    assert_eq!(listing, "0:000: 1650  CLEAR REGISTERS\n0:001: 0071  JSB 016\n");
+    //ROM 8 is 10 in octal.
+    let mut opcodes = vec![u10::new(0o1650); ROM_WORDS * 8];
+    opcodes.push(u10::new(0o0071));
+    assert!(disassemble_rom(&opcodes).ends_with("7:377: 1650  CLEAR REGISTERS\n10:000: 0071  JSB 016\n"));
+  }
+}
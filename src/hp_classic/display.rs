@@ -0,0 +1,112 @@
+//! LED display, driven by the A&R chip.
+//! B masks the digits: 8 or more blanks the digit, 2 lights the decimal point after the digit.
+//! A holds the digits. In the sign positions, 9 shows a minus sign.
+//! The display has 15 positions. The decimal point takes a position of its own, between two digits.
+
+use std::fmt;
+
+/// Digits 13 down to 0 of A and B, from left to right.
+const DIGITS: usize = 14;
+/// 14 digits and the decimal point.
+pub const POSITIONS: usize = 15;
+/// Mantissa sign, and exponent sign.
+const SIGN_POSITIONS: [usize; 2] = [13, 2];
+
+/// Seven segments, bit 0 = a, to bit 6 = g. Bit 7 = decimal point.
+/// `character` is '0' to '9', '-', '.' or ' '.
+pub fn segments(character: char) -> u8 {
+  match character {
+    '0' => 0b0111111,
+    '1' => 0b0000110,
+    '2' => 0b1011011,
+    '3' => 0b1001111,
+    '4' => 0b1100110,
+    '5' => 0b1101101,
+    '6' => 0b1111101,
+    '7' => 0b0000111,
+    '8' => 0b1111111,
+    '9' => 0b1101111,
+    '-' => 0b1000000,
+    '.' => 0b10000000,
+    _ => 0,
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Display {
+  /// Left to right.
+  pub positions: [char; POSITIONS],
+  pub on: bool,
+  /// Every decimal point segment is lit. See `low_battery`.
+  pub low_battery: bool,
+}
+
+impl Display {
+  /// `a` and `b` hold one digit per nibble.
+  pub fn decode(a: u64, b: u64, on: bool) -> Self {
+    let mut positions = [' '; POSITIONS];
+    if on {
+      let mut position = 0;
+      let mut point_shown = false;
+      for index in (0..DIGITS).rev() {
+        let a_digit = (a >> (index * 4)) & 0xF;
+        let b_digit = (b >> (index * 4)) & 0xF;
+        if b_digit < 8 {
+          positions[position] = if SIGN_POSITIONS.contains(&index) {
+            if a_digit == 9 { '-' } else { ' ' }
+          } else {
+            char::from_digit(a_digit.min(9) as u32, 10).unwrap()
+          };
+        }
+        position += 1;
+        //There is only room for one decimal point.
+        if b_digit == 2 && !point_shown {
+          positions[position] = '.';
+          position += 1;
+          point_shown = true;
+        }
+      }
+    }
+    Self { positions, on, low_battery: false }
+  }
+  
+  /// With a low battery, the display driver lights the decimal point segment of every position while the display is on.
+  pub fn low_battery(mut self) -> Self {
+    self.low_battery = self.on;
+    self
+  }
+  
+  /// Segments of each position, as lit on the LEDs.
+  pub fn segments(&self) -> [u8; POSITIONS] {
+    let low_battery = if self.low_battery { segments('.') } else { 0 };
+    self.positions.map(|character| segments(character) | low_battery)
+  }
+}
+
+/// Shows the display as text, for example "-1.234567890-05".
+impl fmt::Display for Display {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for character in self.positions {
+      write!(f, "{}", character)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decimal_point_takes_a_position() {
+    let display = Display::decode(0x91234567890905, 0x02000000000000, true);
+    assert_eq!(display.to_string(), "-1.234567890-05");
+  }
+
+  #[test]
+  fn blanked_and_off() {
+    let display = Display::decode(0x00000000000050, 0x99999999999929, true);
+    assert_eq!(display.to_string(), "            5. ");
+    assert_eq!(Display::decode(0x00000000000005, 0, false).to_string(), " ".repeat(POSITIONS));
+  }
+}
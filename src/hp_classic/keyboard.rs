@@ -0,0 +1,143 @@
+//! Key codes per model. The code is the ROM address "KEYS -> ROM ADDRESS" jumps to.
+//! All classic models share the same key matrix, so the same position has the same code on every model.
+//! The key enums list the keys in matrix order, so their codes come from `KEY_CODES` and their labels from the model's layout.
+
+use super::model;
+
+pub const KEY_COUNT: usize = 35;
+
+/// Key codes from the top left key to the bottom right key.
+/// The top three rows have 5 keys, the bottom five rows have 4 keys.
+pub const KEY_CODES: [u8; KEY_COUNT] = [
+  0o06, 0o04, 0o03, 0o02, 0o00,
+  0o46, 0o44, 0o43, 0o42, 0o40,
+  0o16, 0o14, 0o13, 0o12, 0o10,
+  0o76, 0o73, 0o72, 0o70,
+  0o66, 0o63, 0o62, 0o60,
+  0o56, 0o53, 0o52, 0o50,
+  0o26, 0o23, 0o22, 0o20,
+  0o36, 0o33, 0o32, 0o30,
+];
+
+/// Keys are held for about 50 milliseconds, long enough for the firmware's debounce loop.
+const KEY_HOLD_CYCLES: u32 = 180;
+
+/// A key which is held down for a short while, then released.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct HeldKey(Option<(u8, u32)>);
+
+impl HeldKey {
+  pub(crate) fn press(&mut self, key_code: u8) {
+    self.0 = Some((key_code, KEY_HOLD_CYCLES));
+  }
+  
+  pub(crate) fn release(&mut self) {
+    self.0 = None;
+  }
+  
+  pub(crate) fn is_down(&self) -> bool {
+    self.0.is_some()
+  }
+  
+  /// Key code while the key is down. Each scan takes one cycle off the hold time.
+  pub(crate) fn scan(&mut self) -> Option<u8> {
+    let (key_code, cycles) = self.0?;
+    self.0 = (cycles > 1).then_some((key_code, cycles - 1));
+    Some(key_code)
+  }
+}
+
+/// Anything that can be pressed on a classic calculator.
+pub trait Key: Copy + std::fmt::Debug {
+  fn key_code(self) -> u8;
+}
+
+/// Raw key code, for models without their own key enum. See `model::Model::key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCode(pub u8);
+
+impl Key for KeyCode {
+  fn key_code(self) -> u8 {
+    self.0
+  }
+}
+
+/// Declares a key enum in matrix order. `ALL` must list all 35 keys, so a missing or extra key does not compile.
+macro_rules! keys {
+  ($name:ident, $model:path, [$($key:ident),* $(,)?]) => {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum $name {
+      $($key),*
+    }
+    
+    impl $name {
+      pub const ALL: [$name; KEY_COUNT] = [$($name::$key),*];
+      
+      /// Label printed on the key.
+      pub fn label(self) -> &'static str {
+        $model.layout[self as usize]
+      }
+    }
+    
+    impl Key for $name {
+      fn key_code(self) -> u8 {
+        KEY_CODES[self as usize]
+      }
+    }
+  };
+}
+
+keys!(HP35Key, model::HP35, [
+  Power, Log, Ln, Exp, Clr,
+  Sqrt, Arc, Sin, Cos, Tan,
+  Reciprocal, XExchangeY, RollDown, Sto, Rcl,
+  Enter, Chs, Eex, Clx,
+  Minus, Seven, Eight, Nine,
+  Plus, Four, Five, Six,
+  Multiply, One, Two, Three,
+  Divide, Zero, Point, Pi,
+]);
+
+keys!(HP45Key, model::HP45, [
+  Reciprocal, Ln, Exp, Fix, Shift,
+  Power, Log, Sin, Cos, Tan,
+  XExchangeY, RollDown, Sto, Rcl, Percent,
+  Enter, Chs, Eex, Clx,
+  Minus, Seven, Eight, Nine,
+  Plus, Four, Five, Six,
+  Multiply, One, Two, Three,
+  Divide, Zero, Point, SigmaPlus,
+]);
+
+keys!(HP55Key, model::HP55, [
+  F, G, Bst, Sst, Gto,
+  Power, Log, Sin, Cos, Tan,
+  XExchangeY, RollDown, Sto, Rcl, RunStop,
+  Enter, Chs, Eex, Clx,
+  Minus, Seven, Eight, Nine,
+  Plus, Four, Five, Six,
+  Multiply, One, Two, Three,
+  Divide, Zero, Point, SigmaPlus,
+]);
+
+keys!(HP70Key, model::HP70, [
+  N, I, Pmt, Pv, Fv,
+  Percent, DeltaPercent, SigmaPlus, XExchangeY, Clr,
+  RollDown, Sto, Rcl, M, Sqrt,
+  Enter, Chs, Eex, Clx,
+  Minus, Seven, Eight, Nine,
+  Plus, Four, Five, Six,
+  Multiply, One, Two, Three,
+  Divide, Zero, Point, Power,
+]);
+
+keys!(HP80Key, model::HP80, [
+  N, I, Pmt, Pv, Fv,
+  Shift, Days, Sigma, Percent, Clr,
+  XExchangeY, RollDown, Sto, Rcl, Sqrt,
+  Enter, Chs, Eex, Clx,
+  Minus, Seven, Eight, Nine,
+  Plus, Four, Five, Six,
+  Multiply, One, Two, Three,
+  Divide, Zero, Point, Power,
+]);
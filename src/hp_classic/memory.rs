@@ -0,0 +1,136 @@
+//! Saves and restores the data registers of `HP_RAM`, so stored values survive host restarts.
+//! The file is text: a "HP_RAM <registers>" header line, then one register per line as 14 hex digits, most significant digit first.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chips::ram;
+use arbitrary_int::u56;
+
+#[derive(Debug)]
+pub enum MemoryError {
+  Io(io::Error),
+  /// Missing or malformed header line.
+  Header,
+  /// The file was saved from a data storage chip with a different number of registers.
+  WrongRegisterCount { expected: usize, actual: usize },
+  /// Line number, starting from 1.
+  InvalidRegister { line: usize },
+  /// Something follows the last register. Line number, starting from 1.
+  ExtraLine { line: usize },
+}
+
+impl fmt::Display for MemoryError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      MemoryError::Io(error) => error.fmt(f),
+      MemoryError::Header => write!(f, "Not an HP_RAM memory file"),
+      MemoryError::WrongRegisterCount { expected, actual } => write!(f, "Memory file has {} registers, expected {}", actual, expected),
+      MemoryError::InvalidRegister { line } => write!(f, "Invalid register on line {}", line),
+      MemoryError::ExtraLine { line } => write!(f, "Unexpected line {} after the last register", line),
+    }
+  }
+}
+
+impl std::error::Error for MemoryError {}
+
+impl From<io::Error> for MemoryError {
+  fn from(error: io::Error) -> Self {
+    MemoryError::Io(error)
+  }
+}
+
+const HEADER: &str = "HP_RAM";
+
+pub fn export<const REGS: usize>(ram: &ram::HP_RAM<REGS>) -> String {
+  let mut text = format!("{} {}\n", HEADER, REGS);
+  for reg in &ram.regs {
+    text += &format!("{:014X}\n", reg.value());
+  }
+  text
+}
+
+/// Registers are only changed if the whole file is valid. Blank lines after the last register are allowed, anything else is rejected.
+pub fn import<const REGS: usize>(ram: &mut ram::HP_RAM<REGS>, text: &str) -> Result<(), MemoryError> {
+  let mut lines = text.lines();
+  let count = lines.next()
+    .and_then(|header| header.strip_prefix(HEADER))
+    .and_then(|count| count.trim().parse::<usize>().ok())
+    .ok_or(MemoryError::Header)?;
+  if count != REGS {
+    return Err(MemoryError::WrongRegisterCount { expected: REGS, actual: count });
+  }
+  let mut regs = ram.regs;
+  for (index, reg) in regs.iter_mut().enumerate() {
+    let line = index + 2;
+    let value = lines.next()
+      .and_then(|digits| u64::from_str_radix(digits.trim(), 16).ok())
+      .filter(|&value| value <= u56::MAX.value())
+      .ok_or(MemoryError::InvalidRegister { line })?;
+    *reg = u56::new(value);
+  }
+  if let Some(index) = lines.position(|line| !line.trim().is_empty()) {
+    return Err(MemoryError::ExtraLine { line: REGS + 2 + index });
+  }
+  ram.regs = regs;
+  Ok(())
+}
+
+pub fn save<const REGS: usize>(ram: &ram::HP_RAM<REGS>, path: impl AsRef<Path>) -> io::Result<()> {
+  fs::write(path, export(ram))
+}
+
+pub fn load<const REGS: usize>(ram: &mut ram::HP_RAM<REGS>, path: impl AsRef<Path>) -> Result<(), MemoryError> {
+  import(ram, &fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let mut ram = ram::HP_RAM::<2>::new();
+    ram.regs = [u56::new(0x12345678901234), u56::new(0x90000000000001)];
+    let text = export(&ram);
+    assert_eq!(text, "HP_RAM 2\n12345678901234\n90000000000001\n");
+    let mut imported = ram::HP_RAM::<2>::new();
+    import(&mut imported, &text).unwrap();
+    assert_eq!(imported.regs, ram.regs);
+  }
+
+  #[test]
+  fn header() {
+    let mut ram = ram::HP_RAM::<2>::new();
+    assert!(matches!(import(&mut ram, ""), Err(MemoryError::Header)));
+    assert!(matches!(import(&mut ram, "HP_ROM 2\n"), Err(MemoryError::Header)));
+    assert!(matches!(import(&mut ram, "HP_RAM two\n"), Err(MemoryError::Header)));
+  }
+
+  #[test]
+  fn wrong_register_count() {
+    let mut ram = ram::HP_RAM::<2>::new();
+    assert!(matches!(import(&mut ram, "HP_RAM 10\n"), Err(MemoryError::WrongRegisterCount { expected: 2, actual: 10 })));
+  }
+
+  #[test]
+  fn invalid_register() {
+    let mut ram = ram::HP_RAM::<2>::new();
+    //Too large for 14 digits.
+    assert!(matches!(import(&mut ram, "HP_RAM 2\n0\n100000000000000\n"), Err(MemoryError::InvalidRegister { line: 3 })));
+    assert!(matches!(import(&mut ram, "HP_RAM 2\nXYZ\n0\n"), Err(MemoryError::InvalidRegister { line: 2 })));
+    //Missing register.
+    assert!(matches!(import(&mut ram, "HP_RAM 2\n0\n"), Err(MemoryError::InvalidRegister { line: 3 })));
+  }
+
+  #[test]
+  fn extra_line() {
+    let mut ram = ram::HP_RAM::<2>::new();
+    assert!(matches!(import(&mut ram, "HP_RAM 2\n1\n2\n3\n"), Err(MemoryError::ExtraLine { line: 4 })));
+    assert_eq!(ram.regs, [u56::new(0); 2]);
+    import(&mut ram, "HP_RAM 2\n1\n2\n\n").unwrap();
+    assert_eq!(ram.regs, [u56::new(1), u56::new(2)]);
+  }
+}
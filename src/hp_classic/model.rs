@@ -0,0 +1,100 @@
+//! Model descriptors for the classic line.
+//! Every model uses the same key matrix, so layouts are given as labels in the order of `KEY_CODES`.
+
+use super::keyboard::{KeyCode, KEY_CODES, KEY_COUNT};
+
+/// The bottom five rows are the same on every model, except for the last key.
+macro_rules! layout {
+  ($($top:literal),* ; $last:literal) => {
+    [$($top),*,
+     "ENTER", "CHS", "EEX", "CLX",
+     "-", "7", "8", "9",
+     "+", "4", "5", "6",
+     "×", "1", "2", "3",
+     "÷", "0", ".", $last]
+  };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Model {
+  pub name: &'static str,
+  /// Number of 256 word ROMs.
+  pub rom_count: usize,
+  /// Registers in the data storage chip. This is the `EXTRA_REGS` of the `Board`.
+  pub data_registers: usize,
+  /// Has a stopwatch. The HP-45 hides it behind RCL CHS, the HP-55 has it on the mode switch.
+  pub timer: bool,
+  /// Has the RUN / TIMER / PRGM slide switch. See `Board::set_mode_switch`.
+  pub mode_switch: bool,
+  /// Key labels, in the order of `KEY_CODES`.
+  pub layout: [&'static str; KEY_COUNT],
+}
+
+impl Model {
+  /// Key code for a key label, for example "SIN". Models with a key enum, such as `HP45Key`, can use that instead.
+  pub fn key(&self, label: &str) -> Option<KeyCode> {
+    let position = self.layout.iter().position(|&key| key == label)?;
+    Some(KeyCode(KEY_CODES[position]))
+  }
+}
+
+pub const HP35: Model = Model {
+  name: "HP-35",
+  rom_count: 3,
+  data_registers: 0,
+  timer: false,
+  mode_switch: false,
+  layout: layout!("x^y", "LOG", "LN", "e^x", "CLR",
+                  "√x", "ARC", "SIN", "COS", "TAN",
+                  "1/x", "x⇄y", "R↓", "STO", "RCL"; "π"),
+};
+
+pub const HP45: Model = Model {
+  name: "HP-45",
+  rom_count: 8,
+  data_registers: 10,
+  timer: true,
+  mode_switch: false,
+  layout: layout!("1/x", "LN", "e^x", "FIX", "SHIFT",
+                  "y^x", "LOG", "SIN", "COS", "TAN",
+                  "x⇄y", "R↓", "STO", "RCL", "%"; "Σ+"),
+};
+
+/// The HP-55 times its stopwatch from a crystal. The board keeps it accurate by ignoring turbo while the mode switch is on TIMER.
+pub const HP55: Model = Model {
+  name: "HP-55",
+  rom_count: 12,
+  data_registers: 30,
+  timer: true,
+  mode_switch: true,
+  layout: layout!("f", "g", "BST", "SST", "GTO",
+                  "y^x", "LOG", "SIN", "COS", "TAN",
+                  "x⇄y", "R↓", "STO", "RCL", "R/S"; "Σ+"),
+};
+
+/// The financial registers (n, i, PMT, PV, FV) do not fit in the A&R, which only has room for the stack and one storage register,
+/// so the financial models need a data storage chip. A data storage chip holds 10 registers, as in the HP-45.
+pub const HP70: Model = Model {
+  name: "HP-70",
+  rom_count: 4,
+  data_registers: 10,
+  timer: false,
+  mode_switch: false,
+  layout: layout!("n", "i", "PMT", "PV", "FV",
+                  "%", "Δ%", "Σ+", "x⇄y", "CLR",
+                  "R↓", "STO", "RCL", "M", "√x"; "y^x"),
+};
+
+/// See `HP70` for the data registers.
+pub const HP80: Model = Model {
+  name: "HP-80",
+  rom_count: 8,
+  data_registers: 10,
+  timer: false,
+  mode_switch: false,
+  layout: layout!("n", "i", "PMT", "PV", "FV",
+                  "SHIFT", "DAYS", "Σ", "%", "CLR",
+                  "x⇄y", "R↓", "STO", "RCL", "√x"; "y^x"),
+};
+
+pub const MODELS: [Model; 5] = [HP35, HP45, HP55, HP70, HP80];
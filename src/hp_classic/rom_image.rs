@@ -0,0 +1,183 @@
+//! ROM image formats.
+//! `Board::new` takes opcodes packed 10 bits each, most significant bit first, 256 opcodes (320 bytes) per ROM.
+//! Dumps usually come as ".obj" text, with one octal opcode per line. Some come as hex text, with one opcode per line.
+
+use std::fmt;
+
+use arbitrary_int::u10;
+use log::warn;
+
+pub const ROM_WORDS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomImageError {
+  /// Line number, starting from 1.
+  InvalidOpcode { line: usize },
+  /// Every ROM holds exactly 256 words.
+  WrongWordCount { words: usize },
+}
+
+impl fmt::Display for RomImageError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RomImageError::InvalidOpcode { line } => write!(f, "Invalid opcode on line {}", line),
+      RomImageError::WrongWordCount { words } => write!(f, "{} words is not a multiple of {} words per ROM", words, ROM_WORDS),
+    }
+  }
+}
+
+impl std::error::Error for RomImageError {}
+
+/// Parses one opcode per line in the given radix. Each line may be prefixed with an address and ':'.
+/// Blank lines are skipped.
+fn parse_lines(text: &str, radix: u32) -> Result<Vec<u10>, RomImageError> {
+  let mut opcodes = vec![];
+  for (index, line) in text.lines().enumerate() {
+    let opcode = line.rsplit(':').next().unwrap_or_default().trim();
+    if opcode.is_empty() {
+      continue;
+    }
+    let opcode = u16::from_str_radix(opcode, radix).ok()
+      .filter(|&opcode| opcode <= u10::MAX.value())
+      .ok_or(RomImageError::InvalidOpcode { line: index + 1 })?;
+    opcodes.push(u10::new(opcode));
+  }
+  Ok(opcodes)
+}
+
+/// Parses ".obj" text. Each line is an octal opcode, optionally prefixed with an octal address and ':'.
+pub fn parse_octal(text: &str) -> Result<Vec<u10>, RomImageError> {
+  parse_lines(text, 8)
+}
+
+/// Parses hex text. Each line is a hex opcode, optionally prefixed with a hex address and ':'.
+pub fn parse_hex(text: &str) -> Result<Vec<u10>, RomImageError> {
+  parse_lines(text, 16)
+}
+
+/// Parses ".obj" ROM text for a model, warning if the ROM size or the board's data registers do not match the model.
+pub(crate) fn parse_model(name: &str, rom_words: usize, data_registers: usize, board_registers: usize, obj: &str) -> Result<Vec<u10>, RomImageError> {
+  let opcodes = parse_octal(obj)?;
+  if opcodes.len() != rom_words {
+    warn!("{} has {} ROM words, but got {}.", name, rom_words, opcodes.len());
+  }
+  if board_registers != data_registers {
+    warn!("{} has {} data registers, but the board has {}.", name, data_registers, board_registers);
+  }
+  Ok(opcodes)
+}
+
+/// One 4 digit octal opcode per line.
+pub fn to_octal(opcodes: &[u10]) -> String {
+  opcodes.iter().map(|opcode| format!("{:04o}\n", opcode.value())).collect()
+}
+
+/// One 3 digit hex opcode per line.
+pub fn to_hex(opcodes: &[u10]) -> String {
+  opcodes.iter().map(|opcode| format!("{:03X}\n", opcode.value())).collect()
+}
+
+/// Returns the number of ROMs, if the opcodes fill whole ROMs.
+pub fn validate(opcodes: &[u10]) -> Result<usize, RomImageError> {
+  if opcodes.is_empty() || !opcodes.len().is_multiple_of(ROM_WORDS) {
+    return Err(RomImageError::WrongWordCount { words: opcodes.len() });
+  }
+  Ok(opcodes.len() / ROM_WORDS)
+}
+
+/// Packs opcodes 10 bits each, most significant bit first.
+pub fn pack(opcodes: &[u10]) -> Vec<u8> {
+  let mut packed = Vec::with_capacity((opcodes.len() * 10).div_ceil(8));
+  let mut bits = 0u32;
+  let mut bit_count = 0;
+  for opcode in opcodes {
+    bits = bits << 10 | opcode.value() as u32;
+    bit_count += 10;
+    while bit_count >= 8 {
+      bit_count -= 8;
+      packed.push((bits >> bit_count) as u8);
+    }
+  }
+  if bit_count > 0 {
+    packed.push((bits << (8 - bit_count)) as u8);
+  }
+  packed
+}
+
+/// Unpacks opcodes 10 bits each, most significant bit first. Leftover bits at the end are dropped.
+pub fn unpack(packed: &[u8]) -> Vec<u10> {
+  let mut opcodes = Vec::with_capacity(packed.len() * 8 / 10);
+  let mut bits = 0u32;
+  let mut bit_count = 0;
+  for byte in packed {
+    bits = bits << 8 | *byte as u32;
+    bit_count += 8;
+    if bit_count >= 10 {
+      bit_count -= 10;
+      opcodes.push(u10::new(((bits >> bit_count) & 0x3FF) as u16));
+    }
+  }
+  opcodes
+}
+
+/// Packs whole ROMs, after checking the word count.
+pub fn pack_roms(opcodes: &[u10]) -> Result<Vec<u8>, RomImageError> {
+  validate(opcodes)?;
+  Ok(pack(opcodes))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn opcodes(values: &[u16]) -> Vec<u10> {
+    values.iter().map(|&value| u10::new(value)).collect()
+  }
+
+  #[test]
+  fn packed_rom() {
+    let mut rom = vec![u10::new(0); ROM_WORDS];
+    rom[0] = u10::new(0o1777);
+    rom[1] = u10::new(0o0001);
+    rom[ROM_WORDS - 1] = u10::new(0o1000);
+    let packed = pack_roms(&rom).unwrap();
+    assert_eq!(packed.len(), 320);
+    //1111111111 0000000001 0000000000...
+    assert_eq!(packed[..3], [0xFF, 0xC0, 0x10]);
+    //...0000000000 1000000000
+    assert_eq!(packed[318..], [0x02, 0x00]);
+    assert_eq!(unpack(&packed), rom);
+  }
+
+  #[test]
+  fn round_trip() {
+    let values = opcodes(&[0o1650, 0o0071, 0o0000, 0o1777, 0o0525]);
+    //50 bits, so the last byte is padded.
+    let packed = pack(&values);
+    assert_eq!(packed.len(), 7);
+    assert_eq!(unpack(&packed), values);
+    assert_eq!(parse_octal(&to_octal(&values)), Ok(values.clone()));
+    assert_eq!(parse_hex(&to_hex(&values)), Ok(values));
+  }
+
+  #[test]
+  fn addresses_and_blank_lines() {
+    assert_eq!(parse_octal("0000: 1650\n\n0001:0071\n  \n"), Ok(opcodes(&[0o1650, 0o0071])));
+    assert_eq!(parse_hex("000: 3A8\n001: 039\n"), Ok(opcodes(&[0x3A8, 0x039])));
+  }
+
+  #[test]
+  fn invalid_opcode() {
+    assert_eq!(parse_octal("1650\n0078\n"), Err(RomImageError::InvalidOpcode { line: 2 }));
+    //Too large for 10 bits.
+    assert_eq!(parse_octal("\n2000\n"), Err(RomImageError::InvalidOpcode { line: 2 }));
+    assert_eq!(parse_hex("400\n"), Err(RomImageError::InvalidOpcode { line: 1 }));
+  }
+
+  #[test]
+  fn wrong_word_count() {
+    assert_eq!(validate(&[]), Err(RomImageError::WrongWordCount { words: 0 }));
+    assert_eq!(validate(&vec![u10::new(0); ROM_WORDS * 2]), Ok(2));
+    assert_eq!(pack_roms(&vec![u10::new(0); ROM_WORDS + 1]), Err(RomImageError::WrongWordCount { words: ROM_WORDS + 1 }));
+  }
+}
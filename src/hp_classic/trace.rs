@@ -0,0 +1,36 @@
+//! Per-cycle trace records, for debuggers and logging tools.
+
+use std::fmt;
+
+use arbitrary_int::{u10,u12};
+
+/// State after running one word cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+  /// ROM address the opcode was fetched from.
+  pub address: u8,
+  pub opcode: u10,
+  pub a: u64,
+  pub b: u64,
+  pub c: u64,
+  pub status: u12,
+  pub carry: bool,
+}
+
+impl TraceRecord {
+  pub fn disassemble(&self) -> String {
+    super::disassembler::disassemble(self.opcode)
+  }
+}
+
+impl fmt::Display for TraceRecord {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:03o}: {:04o} {:<24} A={:014X} B={:014X} C={:014X} S={:012b} CY={}",
+      self.address, self.opcode.value(), self.disassemble(), self.a, self.b, self.c, self.status.value(), self.carry as u8)
+  }
+}
+
+/// Trace sink which writes each record to the log at trace level. This is what `run_cycle` used to print.
+pub fn log_trace(record: &TraceRecord) {
+  log::trace!("{}", record);
+}
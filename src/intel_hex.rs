@@ -0,0 +1,115 @@
+//! Intel HEX parsing, for ROM dumps distributed as text.
+//! Supports data, end of file, and extended segment/linear address records.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+  /// Line is missing the ':' start code, or has an odd number of hex digits.
+  Malformed { line: usize },
+  /// Byte count does not match the record length.
+  WrongLength { line: usize },
+  Checksum { line: usize },
+  UnsupportedRecord { line: usize, record_type: u8 },
+  /// Data at or past `max_len`.
+  OutOfRange { line: usize, address: usize },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Malformed { line } => write!(f, "Malformed Intel HEX record on line {}", line),
+      Error::WrongLength { line } => write!(f, "Intel HEX record on line {} has the wrong length", line),
+      Error::Checksum { line } => write!(f, "Intel HEX checksum mismatch on line {}", line),
+      Error::UnsupportedRecord { line, record_type } => write!(f, "Unsupported Intel HEX record type {:02X} on line {}", record_type, line),
+      Error::OutOfRange { line, address } => write!(f, "Intel HEX data on line {} is past the end of the image, at address {:X}", line, address),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+/// Returns the binary image starting from address 0. Gaps between records are filled with zeros.
+/// The image holds at most `max_len` bytes. Data past it is an error, so a bad base address cannot allocate gigabytes.
+pub fn parse(text: &str, max_len: usize) -> Result<Vec<u8>, Error> {
+  let mut image = vec![];
+  let mut base_address = 0;
+  for (index, record) in text.lines().enumerate() {
+    let line = index + 1;
+    let record = record.trim();
+    if record.is_empty() {
+      continue;
+    }
+    let bytes = decode_record(record, line)?;
+    let count = bytes[0] as usize;
+    if bytes.len() != count + 5 {
+      return Err(Error::WrongLength { line });
+    }
+    if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+      return Err(Error::Checksum { line });
+    }
+    let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+    let data = &bytes[4..4 + count];
+    match bytes[3] {
+      0x00 => {
+        let start = base_address + address;
+        if start + count > max_len {
+          return Err(Error::OutOfRange { line, address: start + count - 1 });
+        }
+        if image.len() < start + count {
+          image.resize(start + count, 0);
+        }
+        image[start..start + count].copy_from_slice(data);
+      },
+      0x01 => break,
+      0x02 | 0x04 if count != 2 => return Err(Error::WrongLength { line }),
+      0x02 => base_address = (u16::from_be_bytes([data[0], data[1]]) as usize) << 4,
+      0x04 => base_address = (u16::from_be_bytes([data[0], data[1]]) as usize) << 16,
+      0x03 | 0x05 => {}, //Start address records do not affect the image.
+      record_type => return Err(Error::UnsupportedRecord { line, record_type }),
+    }
+  }
+  Ok(image)
+}
+
+/// Converts ":LLAAAATT..CC" to bytes, without the start code.
+fn decode_record(record: &str, line: usize) -> Result<Vec<u8>, Error> {
+  let hex = record.strip_prefix(':').ok_or(Error::Malformed { line })?;
+  if !hex.is_ascii() || hex.len() % 2 != 0 || hex.len() < 10 {
+    return Err(Error::Malformed { line });
+  }
+  (0..hex.len()).step_by(2)
+    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| Error::Malformed { line }))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn data_and_end_of_file() {
+    let text = ":0300010011223396\n:00000001FF\n:01000000AA55\n";
+    assert_eq!(parse(text, 16), Ok(vec![0x00, 0x11, 0x22, 0x33]));
+  }
+
+  #[test]
+  fn checksum() {
+    assert_eq!(parse(":0300010011223397\n", 16), Err(Error::Checksum { line: 1 }));
+  }
+
+  #[test]
+  fn extended_address() {
+    let segment = ":020000020001FB\n:01000000AA55\n";
+    assert_eq!(parse(segment, 32), Ok([vec![0; 16], vec![0xAA]].concat()));
+    let linear = ":02000004FFFFFC\n:01000000AA55\n";
+    assert_eq!(parse(linear, 32), Err(Error::OutOfRange { line: 2, address: 0xFFFF0000 }));
+    assert_eq!(parse(":0100000400FB\n", 32), Err(Error::WrongLength { line: 1 }));
+  }
+
+  #[test]
+  fn malformed() {
+    assert_eq!(parse("0300010011223396\n", 16), Err(Error::Malformed { line: 1 }));
+    assert_eq!(parse(":0400010011223396\n", 16), Err(Error::WrongLength { line: 1 }));
+  }
+}
@@ -1,3 +1,8 @@
+pub mod bus;
 pub mod busicom141pf;
 pub mod fairchild_ves;
-pub mod hp_classic;
\ No newline at end of file
+pub mod hp_classic;
+pub mod intel_hex;
+pub mod mcs4;
+#[cfg(feature = "i4040")]
+pub mod mcs40;
\ No newline at end of file
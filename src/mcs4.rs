@@ -0,0 +1,411 @@
+//! Intel SIM4-01 / MCS-4 development board
+//! A 4004 with up to 16 4001 ROMs and up to 16 4002 RAMs, with all I/O ports left for the host.
+
+use chips::{rom,ram,cpu,cpu::i4004};
+#[cfg(feature = "i4040")]
+use chips::cpu::i4040;
+use log::warn;
+use crate::bus::{Bus, Region, Target, Handler};
+use arbitrary_int::{u2,u4};
+#[cfg(feature = "i4040")]
+use arbitrary_int::u1;
+
+const ROM_CHIP_LEN: usize = 256;
+const MAX_ROMS: usize = 16;
+
+pub struct Board {
+  /// Private, so that it always matches `rom_bus`. Ports are reached through `rom_ports` and `set_rom_input`.
+  i4001s: Vec<rom::I4001>,
+  /// Installed RAM chips, in bank order. Private, so that it always matches `ram_banks`. See `ram`.
+  i4002s: Vec<ram::I4002>,
+  pub i4004: cpu::I4004,
+  pub program_ram: Option<ProgramRam>,
+  ram_banks: RamBanks,
+  /// Maps the ROM address space onto `i4001s`, as built by `new`.
+  rom_bus: Bus,
+}
+
+/// Number of 4002 chips installed in each of the 4 banks selected by DCL. Each bank holds up to 4 chips.
+pub type RamBanks = [u8; 4];
+
+impl Board {
+  /// The ROM binary is split into 256 byte chips. The last chip is padded with zeros.
+  pub fn new(rom_binary: Vec<u8>, mut ram_banks: RamBanks) -> Self {
+    let i4001s = load_roms(&rom_binary);
+    Self {
+      rom_bus: rom_bus(i4001s.len()),
+      i4001s,
+      i4002s: populate_ram(&mut ram_banks),
+      i4004: cpu::I4004::new(),
+      program_ram: None,
+      ram_banks,
+    }
+  }
+  
+  /// Attaches writable program memory, which takes over the ROM address space from `first_page` onwards.
+  /// WRR to ROM ports 14 and 15 then goes to the program memory, so the ports of ROM chips 14 and 15 no longer change.
+  pub fn add_program_ram(&mut self, first_page: usize, pages: usize) {
+    self.program_ram = Some(ProgramRam::new(first_page, pages));
+  }
+
+  pub fn run_cycle(&mut self) {
+    let mut i4004_io = I4004IO {
+      i4001s: &mut self.i4001s,
+      i4002s: &mut self.i4002s,
+      ram_banks: &self.ram_banks,
+      program_ram: self.program_ram.as_mut(),
+      rom_bus: &self.rom_bus,
+      #[cfg(feature = "i4040")]
+      interrupt: false,
+      #[cfg(feature = "i4040")]
+      rom_bank: None,
+    };
+    self.i4004.run_cycle(&mut i4004_io);
+  }
+  
+  pub fn ram_banks(&self) -> RamBanks {
+    self.ram_banks
+  }
+  
+  /// RAM chip by DCL bank and chip number within the bank. None if it is not installed.
+  pub fn ram(&self, bank: usize, chip: usize) -> Option<&ram::I4002> {
+    ram_position(&self.ram_banks, bank, chip).map(|position| &self.i4002s[position])
+  }
+  
+  /// I/O port of a ROM chip, as last written by either the program or the host. None if there is no such ROM.
+  pub fn rom_ports(&self, chip: usize) -> Option<u4> {
+    self.i4001s.get(chip).map(|i4001| i4001.read_ports())
+  }
+  
+  /// Drives the I/O port of a ROM chip, so the program can read it.
+  pub fn set_rom_input(&mut self, chip: usize, value: u4) {
+    match self.i4001s.get_mut(chip) {
+      Some(i4001) => i4001.write_ports(value),
+      None => warn!("Input to ROM {}, which is not installed.", chip),
+    }
+  }
+  
+  /// Output port of a RAM chip, by DCL bank and chip number within the bank. None if it is not installed.
+  pub fn ram_ports(&self, bank: usize, chip: usize) -> Option<u4> {
+    self.ram(bank, chip).map(|i4002| i4002.read_ports())
+  }
+}
+
+/// Program memory attached through a 4008/4009 pair or a 4289, following the Intellec 4 wiring:
+/// * WRR to ROM port 14 latches the page (upper 4 address bits) to write to.
+/// * WRR to ROM port 15, bit 0 enables writing.
+/// * SRC supplies the lower 8 address bits.
+/// * WPM is used in pairs. The first writes the upper nibble, the second writes the lower nibble.
+///
+/// WPM reaches the board through `i4004::IO::write_program_ram`, which needs the `wpm` feature and a chips version providing it.
+/// Without it, the program can still run from `data`, which the host fills.
+pub struct ProgramRam {
+  pub data: Vec<u8>,
+  first_page: usize,
+  page: u4,
+  write_enable: bool,
+  upper_nibble: Option<u4>,
+}
+
+impl ProgramRam {
+  pub fn new(first_page: usize, pages: usize) -> Self {
+    Self {
+      data: vec![0; pages * ROM_CHIP_LEN],
+      first_page,
+      page: u4::new(0),
+      write_enable: false,
+      upper_nibble: None,
+    }
+  }
+  
+  /// Position within `data`, if the page is covered by the program memory.
+  fn index(&self, page: usize, offset: u8) -> Option<usize> {
+    let index = page.checked_sub(self.first_page)? * ROM_CHIP_LEN + offset as usize;
+    (index < self.data.len()).then_some(index)
+  }
+  
+  fn read(&self, page: usize, offset: u8) -> Option<u8> {
+    self.index(page, offset).map(|index| self.data[index])
+  }
+  
+  /// WRR to ROM port 14 or 15. Returns false for the other ports, which are left to the ROM chips.
+  fn write_rom_port(&mut self, port: usize, value: u4) -> bool {
+    match port {
+      14 => self.page = value,
+      15 => self.write_enable = value.value() & 0b1 == 0b1,
+      _ => return false,
+    }
+    true
+  }
+  
+  /// One WPM. `offset` is the lower 8 address bits, as sent by SRC.
+  pub fn write_nibble(&mut self, offset: u8, value: u4) {
+    if !self.write_enable {
+      warn!("WPM while program memory writing is disabled.");
+      return;
+    }
+    let upper = match self.upper_nibble.take() {
+      Some(upper) => upper,
+      None => {
+        self.upper_nibble = Some(value);
+        return;
+      }
+    };
+    match self.index(self.page.value() as usize, offset) {
+      Some(index) => self.data[index] = upper.value() << 4 | value.value(),
+      None => warn!("WPM to page {} which has no program memory.", self.page),
+    }
+  }
+}
+
+/// Splits the binary into 256 byte chips. The last chip is padded with zeros.
+pub(crate) fn load_roms(rom_binary: &[u8]) -> Vec<rom::I4001> {
+  let mut i4001s = vec![];
+  for chunk in rom_binary.chunks(ROM_CHIP_LEN) {
+    let mut padded_chunk = Vec::from(chunk);
+    padded_chunk.resize_with(ROM_CHIP_LEN, Default::default);
+    i4001s.push(rom::I4001::new(padded_chunk.try_into().unwrap()));
+  }
+  if i4001s.len() > MAX_ROMS {
+    warn!("Only {} ROMs can be addressed. Dropping {} ROMs.", MAX_ROMS, i4001s.len() - MAX_ROMS);
+    i4001s.truncate(MAX_ROMS);
+  }
+  if i4001s.is_empty() {
+    warn!("No ROM data. Using an empty ROM.");
+    i4001s.push(rom::I4001::new([0; ROM_CHIP_LEN]));
+  }
+  i4001s
+}
+
+/// ROM address space of a 4004 with this many ROM chips. Addresses past the last chip wrap around.
+pub(crate) fn rom_bus(chips: usize) -> Bus {
+  let mut regions: Vec<Region> = (0..chips).map(|chip| Region::new(chip * ROM_CHIP_LEN..(chip + 1) * ROM_CHIP_LEN, Target::Rom(chip))).collect();
+  if chips < MAX_ROMS {
+    regions.push(Region::new(chips * ROM_CHIP_LEN..MAX_ROMS * ROM_CHIP_LEN, Target::Mirror { start: 0, mirrored_len: chips * ROM_CHIP_LEN }));
+  }
+  Bus::new("ROM", regions, 0)
+}
+
+/// Creates the RAM chips for the banks. Banks holding more than 4 chips are trimmed.
+pub(crate) fn populate_ram(ram_banks: &mut RamBanks) -> Vec<ram::I4002> {
+  for chips in ram_banks.iter_mut() {
+    if *chips > 4 {
+      warn!("A RAM bank can only hold 4 chips, not {}.", chips);
+      *chips = 4;
+    }
+  }
+  let count = ram_banks.iter().map(|&chips| chips as usize).sum();
+  (0..count).map(|_| ram::I4002::new()).collect()
+}
+
+/// Returns the position within the installed RAM chips, or None if no chip is installed there.
+fn convert_ram_index(ram_banks: &RamBanks, command_control: u4, designated_index: i4004::DesignatedIndex) -> Option<usize> {
+  let bank = match command_control.value() {
+    0b000 => 0,
+    0b001 => 1,
+    0b010 => 2,
+    0b100 => 3,
+    _ => { warn!("Invalid command control register: {}", command_control);
+      0
+    },
+  };
+  ram_position(ram_banks, bank, designated_index.chip_index().value() as usize)
+}
+
+/// Position within the installed RAM chips of a chip in a bank, or None if no chip is installed there.
+pub(crate) fn ram_position(ram_banks: &RamBanks, bank: usize, chip: usize) -> Option<usize> {
+  if chip >= *ram_banks.get(bank)? as usize {
+    return None;
+  }
+  //Chips of the earlier banks come first.
+  let offset: usize = ram_banks[..bank].iter().map(|&chips| chips as usize).sum();
+  Some(offset + chip)
+}
+
+/// Wires the 4004 or 4040 to its ROMs and RAMs.
+/// `i4001s` is the ROM bank currently selected. The 4004 only has one bank.
+pub(crate) struct I4004IO<'a> {
+  pub(crate) i4001s: &'a mut [rom::I4001],
+  pub(crate) i4002s: &'a mut [ram::I4002],
+  pub(crate) ram_banks: &'a RamBanks,
+  pub(crate) program_ram: Option<&'a mut ProgramRam>,
+  /// Built by `rom_bus` for `i4001s`.
+  pub(crate) rom_bus: &'a Bus,
+  /// 4040 interrupt input
+  #[cfg(feature = "i4040")]
+  pub(crate) interrupt: bool,
+  /// 4040 ROM bank selected by DB0/DB1 during this cycle. It takes effect from the next instruction fetch.
+  #[cfg(feature = "i4040")]
+  pub(crate) rom_bank: Option<u1>,
+}
+impl I4004IO<'_> {
+  /// RAM chip selected by DCL and SRC. None if it is not installed.
+  fn ram(&self, command_control: u4, designated_index: i4004::DesignatedIndex) -> Option<&ram::I4002> {
+    convert_ram_index(self.ram_banks, command_control, designated_index).map(|high_addr| &self.i4002s[high_addr])
+  }
+  fn ram_mut(&mut self, command_control: u4, designated_index: i4004::DesignatedIndex) -> Option<&mut ram::I4002> {
+    convert_ram_index(self.ram_banks, command_control, designated_index).map(|high_addr| &mut self.i4002s[high_addr])
+  }
+}
+
+impl Handler for I4004IO<'_> {
+  fn read(&self, target: Target, offset: usize) -> u8 {
+    match target {
+      Target::Rom(chip) if chip < self.i4001s.len() => self.i4001s[chip].read(offset as u8),
+      _ => {
+        warn!("ROM bus read from {:?}.", target);
+        0
+      },
+    }
+  }
+  fn write(&mut self, target: Target, _offset: usize, value: u8) {
+    warn!("ROM bus write of {:02X} to {:?}.", value, target);
+  }
+}
+
+impl i4004::IO for I4004IO<'_> {
+  fn read_rom_byte(&self, address: i4004::ROMAddress) -> u8 {
+    let high_addr = address.chip_index().value() as usize;
+    let low_addr = address.offset();
+    if let Some(byte) = self.program_ram.as_ref().and_then(|program_ram| program_ram.read(high_addr, low_addr)) {
+      return byte;
+    }
+    self.rom_bus.read(high_addr * ROM_CHIP_LEN + low_addr as usize, self)
+  }
+  
+  fn read_rom_ports(&self, designated_index: i4004::DesignatedIndex) -> u4 {
+    let high_addr = (designated_index.chip_index() << 2 | designated_index.reg_index()).value() as usize;
+    let i4001 = &self.i4001s[high_addr % self.i4001s.len()];  //Wrap around
+    i4001.read_ports()
+  }
+  fn write_rom_ports(&mut self, designated_index: i4004::DesignatedIndex, value: u4) {
+    let high_addr = (designated_index.chip_index() << 2 | designated_index.reg_index()).value() as usize;
+    if let Some(program_ram) = &mut self.program_ram {
+      if program_ram.write_rom_port(high_addr, value) {
+        return;
+      }
+    }
+    let i4001 = &mut self.i4001s[high_addr % self.i4001s.len()];  //Wrap around
+    i4001.write_ports(value);
+  }
+  
+  fn read_ram_character(&self, command_control: u4, designated_index: i4004::DesignatedIndex) -> u4 {
+    match self.ram(command_control, designated_index) {
+      Some(i4002) => i4002.read_character(designated_index.reg_index(), designated_index.char_index()),
+      None => {
+        warn!("Read from nonexisting ram. Command control: {} Chip: {}", command_control, designated_index.chip_index());
+        u4::new(0)
+      },
+    }
+  }
+  fn write_ram_character(&mut self, command_control: u4, designated_index: i4004::DesignatedIndex, value: u4) {
+    match self.ram_mut(command_control, designated_index) {
+      Some(i4002) => i4002.write_character(designated_index.reg_index(), designated_index.char_index(), value),
+      None => warn!("Write to nonexisting ram. Command control: {} Chip: {}", command_control, designated_index.chip_index()),
+    }
+  }
+  fn read_ram_status(&self, command_control: u4, designated_index: i4004::DesignatedIndex, status_index: u2) -> u4 {
+    match self.ram(command_control, designated_index) {
+      Some(i4002) => i4002.read_status(designated_index.reg_index(), status_index),
+      None => {
+        warn!("Read from nonexisting ram. Command control: {} Chip: {}", command_control, designated_index.chip_index());
+        u4::new(0)
+      },
+    }
+  }
+  fn write_ram_status(&mut self, command_control: u4, designated_index: i4004::DesignatedIndex, status_index: u2, value: u4) {
+    match self.ram_mut(command_control, designated_index) {
+      Some(i4002) => i4002.write_status(designated_index.reg_index(), status_index, value),
+      None => warn!("Write to nonexisting ram. Command control: {} Chip: {}", command_control, designated_index.chip_index()),
+    }
+  }
+  fn write_ram_ports(&mut self, command_control: u4, designated_index: i4004::DesignatedIndex, value: u4) {
+    match self.ram_mut(command_control, designated_index) {
+      Some(i4002) => i4002.write_ports(value),
+      None => warn!("Write to nonexisting ram. Command control: {} Chip: {}", command_control, designated_index.chip_index()),
+    }
+  }
+  
+  #[cfg(feature = "wpm")]
+  fn write_program_ram(&mut self, designated_index: i4004::DesignatedIndex, value: u4) {
+    let offset = designated_index.chip_index().value() << 6 | designated_index.reg_index().value() << 4 | designated_index.char_index().value();
+    match &mut self.program_ram {
+      Some(program_ram) => program_ram.write_nibble(offset, value),
+      None => warn!("WPM without program memory."),
+    }
+  }
+}
+
+#[cfg(feature = "i4040")]
+impl i4040::IO for I4004IO<'_> {
+  fn select_rom_bank(&mut self, bank: u1) {
+    self.rom_bank = Some(bank);
+  }
+  
+  fn interrupt(&self) -> bool {
+    self.interrupt
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Program memory for pages 4 and 5, with writing enabled on page 5.
+  fn program_ram() -> ProgramRam {
+    let mut program_ram = ProgramRam::new(4, 2);
+    assert!(program_ram.write_rom_port(14, u4::new(5)));
+    assert!(program_ram.write_rom_port(15, u4::new(1)));
+    program_ram
+  }
+
+  #[test]
+  fn nibble_pairs() {
+    let mut program_ram = program_ram();
+    program_ram.write_nibble(0x12, u4::new(0xA));
+    assert_eq!(program_ram.read(5, 0x12), Some(0));
+    program_ram.write_nibble(0x12, u4::new(0xB));
+    assert_eq!(program_ram.read(5, 0x12), Some(0xAB));
+    program_ram.write_nibble(0x13, u4::new(0xC));
+    program_ram.write_nibble(0x13, u4::new(0xD));
+    assert_eq!(program_ram.read(5, 0x13), Some(0xCD));
+    assert_eq!(program_ram.data[ROM_CHIP_LEN + 0x13], 0xCD);
+  }
+
+  #[test]
+  fn write_enable() {
+    let mut program_ram = program_ram();
+    program_ram.write_rom_port(15, u4::new(0));
+    program_ram.write_nibble(0x12, u4::new(0xA));
+    program_ram.write_nibble(0x12, u4::new(0xB));
+    assert_eq!(program_ram.read(5, 0x12), Some(0));
+    //Dropped nibbles do not leave half a pair behind.
+    program_ram.write_rom_port(15, u4::new(1));
+    program_ram.write_nibble(0x12, u4::new(0x1));
+    program_ram.write_nibble(0x12, u4::new(0x2));
+    assert_eq!(program_ram.read(5, 0x12), Some(0x12));
+  }
+
+  #[test]
+  fn pages() {
+    let mut program_ram = program_ram();
+    program_ram.write_rom_port(14, u4::new(4));
+    program_ram.write_nibble(0xFF, u4::new(0x3));
+    program_ram.write_nibble(0xFF, u4::new(0x4));
+    assert_eq!(program_ram.read(4, 0xFF), Some(0x34));
+    //Pages outside the program memory are left to the ROMs.
+    program_ram.write_rom_port(14, u4::new(6));
+    program_ram.write_nibble(0, u4::new(0x5));
+    program_ram.write_nibble(0, u4::new(0x6));
+    assert_eq!(program_ram.read(6, 0), None);
+    assert_eq!(program_ram.read(3, 0), None);
+    assert_eq!(program_ram.data.iter().filter(|&&byte| byte != 0).count(), 1);
+  }
+
+  #[test]
+  fn other_ports() {
+    let mut program_ram = program_ram();
+    assert!(!program_ram.write_rom_port(13, u4::new(1)));
+    assert!(!program_ram.write_rom_port(0, u4::new(1)));
+  }
+}
@@ -0,0 +1,126 @@
+//! Intel MCS-40 board
+//! The 4040 is bus compatible with the 4004, and adds interrupts, halt, a second index register bank,
+//! and a second ROM bank of up to 16 ROMs, selected by the DB0/DB1 instructions.
+//! Needs the `i4040` feature, and a chips version providing the 4040.
+
+use chips::{rom,ram,cpu};
+use log::warn;
+use arbitrary_int::{u1,u4};
+
+use crate::mcs4::{self, I4004IO, RamBanks};
+use crate::bus::Bus;
+
+pub struct Board {
+  /// ROM bank 0 and ROM bank 1. Private, so that they always match `rom_buses`.
+  i4001s: [Vec<rom::I4001>; 2],
+  /// Installed RAM chips, in bank order. Private, so that it always matches `ram_banks`. See `ram`.
+  i4002s: Vec<ram::I4002>,
+  pub i4040: cpu::I4040,
+  /// External interrupt input. It stays asserted until the host clears it, just like the INT pin.
+  pub interrupt: bool,
+  rom_bank: u1,
+  ram_banks: RamBanks,
+  /// Address space of each ROM bank.
+  rom_buses: [Bus; 2],
+}
+
+impl Board {
+  /// Each binary is split into 256 byte chips. Bank 1 may be empty.
+  pub fn new(rom_bank0: Vec<u8>, rom_bank1: Vec<u8>, mut ram_banks: RamBanks) -> Self {
+    let i4001s = [mcs4::load_roms(&rom_bank0), mcs4::load_roms(&rom_bank1)];
+    Self {
+      rom_buses: [mcs4::rom_bus(i4001s[0].len()), mcs4::rom_bus(i4001s[1].len())],
+      i4001s,
+      i4002s: mcs4::populate_ram(&mut ram_banks),
+      i4040: cpu::I4040::new(),
+      interrupt: false,
+      rom_bank: u1::new(0),
+      ram_banks,
+    }
+  }
+
+  pub fn run_cycle(&mut self) {
+    let [bank0, bank1] = &mut self.i4001s;
+    let i4001s = if self.rom_bank == u1::new(0) { bank0 } else { bank1 };
+    let mut i4040_io = I4004IO {
+      i4001s,
+      i4002s: &mut self.i4002s,
+      ram_banks: &self.ram_banks,
+      program_ram: None,
+      rom_bus: &self.rom_buses[self.rom_bank.value() as usize],
+      interrupt: self.interrupt,
+      rom_bank: None,
+    };
+    self.i4040.run_cycle(&mut i4040_io);
+    if let Some(rom_bank) = i4040_io.rom_bank {
+      self.rom_bank = rom_bank;
+    }
+  }
+  
+  /// ROM bank the next instruction is fetched from.
+  pub fn rom_bank(&self) -> u1 {
+    self.rom_bank
+  }
+  
+  pub fn ram_banks(&self) -> RamBanks {
+    self.ram_banks
+  }
+  
+  /// RAM chip by DCL bank and chip number within the bank. None if it is not installed.
+  pub fn ram(&self, bank: usize, chip: usize) -> Option<&ram::I4002> {
+    mcs4::ram_position(&self.ram_banks, bank, chip).map(|position| &self.i4002s[position])
+  }
+  
+  /// I/O port of a ROM chip in the given bank. None if there is no such ROM.
+  pub fn rom_ports(&self, bank: u1, chip: usize) -> Option<u4> {
+    self.i4001s[bank.value() as usize].get(chip).map(|i4001| i4001.read_ports())
+  }
+  
+  /// Drives the I/O port of a ROM chip in the given bank, so the program can read it.
+  pub fn set_rom_input(&mut self, bank: u1, chip: usize, value: u4) {
+    match self.i4001s[bank.value() as usize].get_mut(chip) {
+      Some(i4001) => i4001.write_ports(value),
+      None => warn!("Input to ROM {} of bank {}, which is not installed.", chip, bank),
+    }
+  }
+  
+  /// Output port of a RAM chip, by DCL bank and chip number within the bank. None if it is not installed.
+  pub fn ram_ports(&self, bank: usize, chip: usize) -> Option<u4> {
+    self.ram(bank, chip).map(|i4002| i4002.read_ports())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DB0: u8 = 0x08;
+  const DB1: u8 = 0x09;
+
+  /// Runs until the ROM bank changes, for up to a few instructions.
+  fn run_until_bank(board: &mut Board, bank: u1) -> bool {
+    (0..64).any(|_| {
+      board.run_cycle();
+      board.rom_bank() == bank
+    })
+  }
+
+  #[test]
+  fn bank_switching() {
+    //Bank 0 switches to bank 1. Only bank 1 switches back, so returning to bank 0 shows that bank 1 was fetched from.
+    let mut board = Board::new(vec![DB1], vec![0, 0, DB0], [0; 4]);
+    assert_eq!(board.rom_bank(), u1::new(0));
+    assert!(run_until_bank(&mut board, u1::new(1)));
+    assert!(run_until_bank(&mut board, u1::new(0)));
+  }
+
+  #[test]
+  fn ports_by_bank() {
+    let mut board = Board::new(vec![0; 256], vec![], [0; 4]);
+    board.set_rom_input(u1::new(0), 0, u4::new(5));
+    assert_eq!(board.rom_ports(u1::new(0), 0), Some(u4::new(5)));
+    //Bank 1 was left empty, which gets a single empty ROM.
+    assert_eq!(board.rom_ports(u1::new(1), 0), Some(u4::new(0)));
+    assert_eq!(board.rom_ports(u1::new(1), 1), None);
+  }
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use boards::busicom141pf;
+
+fn load_board() -> busicom141pf::Board {
+  let [binary0, binary1, binary2, binary3, binary4] = ["0009", "0010", "0011", "0012", "0013"]
+    .map(|number| common::read_rom(&format!("busicom141pf/roms/4001-{}.bin", number)));
+  busicom141pf::Board::new(binary0, binary1, binary2, binary3, binary4)
+}
+
+#[test]
+#[ignore = "needs ROM dumps in ../busicom141pf/roms"]
+fn addition_prints_total() {
+  let mut board = load_board();
+  board.enable_paper_tape();
+  board.run_script("12 + 34 =");
+  //15 digit columns, a blank column, and the 2 symbol columns.
+  let expected = format!("{:>15} + ", 46);
+  assert_eq!(board.paper_tape().last(), Some(&expected), "Paper tape: {:#?}", board.paper_tape());
+}
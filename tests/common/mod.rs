@@ -0,0 +1,17 @@
+//! Shared by the tests which run real firmware.
+//! ROM dumps are not part of this repository. They are read from the directories next to it, like the examples do,
+//! so these tests are ignored by default. Run them with `cargo test -- --ignored` once the dumps are in place.
+
+//Each test crate only uses some of these.
+#![allow(dead_code)]
+
+/// Reads a ROM dump, relative to the directory holding this repository. Panics if it is missing, so a test never passes without it.
+pub fn read_rom(path: &str) -> Vec<u8> {
+  let path = format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), path);
+  std::fs::read(&path).unwrap_or_else(|error| panic!("{} can not be read: {}", path, error))
+}
+
+/// Reads ".obj" ROM text. See `read_rom`.
+pub fn read_obj(path: &str) -> String {
+  String::from_utf8(read_rom(path)).expect("ROM text is not UTF-8")
+}
@@ -0,0 +1,69 @@
+//! HP-35 keystroke sequences on ROM versions 2 and 4, checking the exact display.
+
+mod common;
+
+use boards::hp_classic::{self, HP35, HP35Key, RunCycles};
+use HP35Key::*;
+
+/// Keystrokes, and the display they must leave, trimmed.
+type Case = (&'static str, &'static [HP35Key], &'static str);
+
+/// Results shared by every ROM version.
+const CASES: [Case; 6] = [
+  ("2 ENTER 3 +", &[Two, Enter, Three, Plus], "5."),
+  ("pi", &[Pi], "3.141592654"),
+  ("2 sqrt", &[Two, Sqrt], "1.414213562"),
+  ("1 e^x", &[One, Exp], "2.718281828"),
+  ("100 log", &[One, Zero, Zero, Log], "2."),
+  ("7 ENTER 2 -", &[Seven, Enter, Two, Minus], "5."),
+];
+
+/// The first HP-35s lost the fraction of 2.02 ln e^x, which ROM version 4 fixed.
+const LN_EXP: &[HP35Key] = &[Two, Point, Zero, Two, Ln, Exp];
+
+fn load_rom(version: &str) -> String {
+  common::read_obj(&format!("hp35/roms/35{}.obj", version))
+}
+
+fn run_keys(obj: &str, keys: &[HP35Key]) -> String {
+  let mut board: HP35 = hp_classic::hp35(obj).expect("Invalid ROM dump");
+  board.run_cycles(2000);
+  for &key in keys {
+    board.press_key(key);
+    board.run_cycles(2000);
+  }
+  //Transcendental functions take up to a second.
+  board.run_cycles(5000);
+  board.display().to_string().trim().to_string()
+}
+
+fn check_cases(version: &str) {
+  let obj = load_rom(version);
+  for (name, keys, expected) in CASES {
+    assert_eq!(run_keys(&obj, keys), expected, "{} on {}", name, version);
+  }
+}
+
+#[test]
+#[ignore = "needs ROM dumps in ../hp35/roms"]
+fn v2_cases() {
+  check_cases("v2");
+}
+
+#[test]
+#[ignore = "needs ROM dumps in ../hp35/roms"]
+fn v4_cases() {
+  check_cases("v4");
+}
+
+#[test]
+#[ignore = "needs ROM dumps in ../hp35/roms"]
+fn v2_ln_exp_bug() {
+  assert_eq!(run_keys(&load_rom("v2"), LN_EXP), "2.");
+}
+
+#[test]
+#[ignore = "needs ROM dumps in ../hp35/roms"]
+fn v4_ln_exp_fixed() {
+  assert_eq!(run_keys(&load_rom("v4"), LN_EXP), "2.02");
+}
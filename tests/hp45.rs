@@ -0,0 +1,55 @@
+mod common;
+
+use std::time::Duration;
+
+use boards::hp_classic::{self, HP45, HP45Key, RunCycles};
+use HP45Key::*;
+
+/// At authentic speed.
+const ONE_SECOND: u32 = 3571;
+
+fn load_board() -> HP45 {
+  hp_classic::hp45(&common::read_obj("hp45/roms/45.obj")).expect("Invalid ROM dump")
+}
+
+fn type_keys(board: &mut HP45, keys: &[HP45Key]) {
+  board.run_cycles(2000);
+  for &key in keys {
+    board.press_key(key);
+    board.run_cycles(2000);
+  }
+}
+
+#[test]
+#[ignore = "needs ROM dumps in ../hp45/roms"]
+fn stored_register_survives_calculation() {
+  let mut board = load_board();
+  type_keys(&mut board, &[
+    Five, Sto, One,
+    Two, Enter, Three, Multiply,
+    Clx, Rcl, One,
+  ]);
+  assert_eq!(board.display().to_string().trim(), "5.00");
+}
+
+#[test]
+#[ignore = "needs ROM dumps in ../hp45/roms"]
+fn running_stopwatch_ignores_turbo() {
+  let mut board = load_board();
+  type_keys(&mut board, &[Rcl, Chs]);
+  board.run_cycles(ONE_SECOND);
+  if !board.timer_running() {
+    //CHS starts and stops the stopwatch.
+    type_keys(&mut board, &[Chs]);
+    board.run_cycles(ONE_SECOND);
+  }
+  assert!(board.timer_running(), "The stopwatch did not start. Display: {}", board.display());
+  board.turbo = true;
+  let before = board.display();
+  assert_eq!(board.run_for(Duration::from_secs(1)), ONE_SECOND as u64);
+  assert_ne!(board.display(), before, "The stopwatch did not run.");
+  
+  type_keys(&mut board, &[Chs]);
+  board.run_cycles(2 * ONE_SECOND);
+  assert!(!board.timer_running(), "The stopwatch did not stop. Display: {}", board.display());
+}